@@ -0,0 +1,141 @@
+use crate::config::Sources;
+use crate::download::{compare_checksum, download_file, verify_checksum};
+use crate::http::Client;
+use crate::mirror::rank_mirrors;
+use crate::release::{fetch_releases, select_release, GoFile, GoRelease};
+use crate::signature::{download_signature, signature_path, Keyring, CUSTOM_KEYRING_FILE, GO_SIGNING_KEY_FILE};
+use crate::version::{GoVersion, VersionReq};
+use anyhow::{bail, Context, Result};
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+// The release metadata snapshot at the root of a bundle, in the go.dev `?mode=json` format but
+// listing only the bundled archives.
+const BUNDLE_RELEASES: &str = "releases.json";
+
+// A directory holding Go archives together with the release metadata they are verified
+// against, for installing on machines that cannot reach go.dev or a mirror.
+pub struct Bundle {
+    pub dir: PathBuf,
+}
+
+impl Bundle {
+    pub fn new(dir: &Path) -> Bundle {
+        Bundle { dir: dir.to_path_buf() }
+    }
+
+    // The bundled releases, newest first.
+    pub fn releases(&self) -> Result<Vec<GoRelease>> {
+        let path = self.dir.join(BUNDLE_RELEASES);
+        let file = File::open(&path)
+            .with_context(|| format!("'{}' is not a bundle (no {})", self.dir.display(), BUNDLE_RELEASES))?;
+        serde_json::from_reader(file).with_context(|| format!("Corrupt bundle metadata '{}'", path.display()))
+    }
+
+    // The keys the bundle was exported with: a custom keyring as is, the Go signing key pinned.
+    pub fn keyring(&self) -> Result<Option<Keyring>> {
+        if self.dir.join(CUSTOM_KEYRING_FILE).is_file() {
+            return Keyring::custom(&self.dir.join(CUSTOM_KEYRING_FILE)).map(Some);
+        }
+        if self.dir.join(GO_SIGNING_KEY_FILE).is_file() {
+            return Keyring::pinned(&self.dir.join(GO_SIGNING_KEY_FILE)).map(Some);
+        }
+        Ok(None)
+    }
+
+    // The bundled archive for a release, checked against the snapshot's checksum.
+    pub fn tarball(&self, file: &GoFile) -> Result<PathBuf> {
+        let path = self.dir.join(&file.filename);
+        if !path.is_file() {
+            bail!("The bundle lists {} but '{}' is missing", file.version, path.display());
+        }
+        verify_checksum(&file.sha256, &path)?;
+        Ok(path)
+    }
+
+    // Downloads the archives matching each version requirement for each <os>-<arch> platform,
+    // verifies them and records their metadata. The signatures and the key they were checked
+    // against go into the bundle too. Exporting into an existing bundle adds to it.
+    pub fn export(
+        &self,
+        client: &Client,
+        sources: &Sources,
+        keyring: Option<&Keyring>,
+        reqs: &[VersionReq],
+        platforms: &[String],
+    ) -> Result<usize> {
+        let upstream = fetch_releases(client, sources, true)?;
+        let mut bundled = if self.dir.join(BUNDLE_RELEASES).is_file() { self.releases()? } else { Vec::new() };
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create '{}'", self.dir.display()))?;
+
+        // Everything is resolved before downloading, so a typo fails fast.
+        let mut files = Vec::new();
+        for req in reqs {
+            for platform in platforms {
+                let Some((os, arch)) = platform.split_once('-') else {
+                    bail!("Invalid platform '{}' (expected <os>-<arch>, e.g. linux-amd64)", platform);
+                };
+                let file = select_release(&upstream, os, arch, req)?;
+                println!("✔ Found Go Version: {} for {} ({})", file.version, platform, req);
+                files.push(file);
+            }
+        }
+
+        if let Some(keyring) = keyring {
+            let path = self.dir.join(keyring.bundle_file());
+            fs::write(&path, keyring.key()).with_context(|| format!("Failed to write '{}'", path.display()))?;
+        }
+        let exported = files.len();
+        for file in files {
+            let path = self.dir.join(&file.filename);
+            if path.is_file() && verify_checksum(&file.sha256, &path).is_ok() {
+                println!("- {} is already in the bundle", file.filename);
+            } else {
+                let urls = rank_mirrors(client, &sources.tarball_urls(&file.filename), file.size);
+                let checksum = download_file(client, &urls, &path, file.size)?;
+                if let Err(err) = compare_checksum(&file.sha256, &checksum) {
+                    let _ = fs::remove_file(&path);
+                    return Err(err);
+                }
+                println!("✔ Checksum Verified (against {})", sources.metadata_url);
+            }
+            if let Some(keyring) = keyring {
+                let signature = signature_path(&path);
+                if !signature.is_file() {
+                    download_signature(client, &sources.tarball_urls(&file.filename), &signature)?;
+                }
+                match keyring.verify(&path, &signature) {
+                    Ok(key) => println!("✔ Signature Verified (key {})", key),
+                    Err(err) => {
+                        let _ = fs::remove_file(&signature);
+                        return Err(err);
+                    }
+                }
+            }
+            add_file(&mut bundled, &upstream, file);
+        }
+
+        // The snapshot is written last, so it never lists an archive that is not there.
+        bundled.sort_by_key(|r| Reverse(GoVersion::parse(&r.version).ok()));
+        let path = self.dir.join(BUNDLE_RELEASES);
+        serde_json::to_writer_pretty(File::create(&path)?, &bundled)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        Ok(exported)
+    }
+}
+
+// Adds a file to the snapshot under its release, taking the release's details from upstream.
+fn add_file(bundled: &mut Vec<GoRelease>, upstream: &[GoRelease], file: GoFile) {
+    let index = match bundled.iter().position(|r| r.version == file.version) {
+        Some(index) => index,
+        None => {
+            let stable = upstream.iter().find(|r| r.version == file.version).is_some_and(|r| r.stable);
+            bundled.push(GoRelease { version: file.version.clone(), stable, files: Vec::new() });
+            bundled.len() - 1
+        }
+    };
+    let files = &mut bundled[index].files;
+    files.retain(|f| f.filename != file.filename);
+    files.push(file);
+}
@@ -0,0 +1,180 @@
+use crate::release::GoFile;
+use anyhow::{bail, Context, Result};
+use std::cmp::Reverse;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CACHE_DIR: &str = "go-installer";
+// The release metadata a cached tarball was verified against, stored next to it.
+const ENTRY_META: &str = "file.json";
+pub const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+// Content-addressed store of downloaded tarballs: each lives in <dir>/<sha256>/<filename>, so
+// a file is only ever reused for the exact checksum it was downloaded for.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    pub dir: PathBuf,
+}
+
+// A tarball in the cache. `path` is None while its download is still incomplete.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub file: GoFile,
+    pub path: Option<PathBuf>,
+    pub size: u64,
+    pub last_used: SystemTime,
+    dir: PathBuf,
+}
+
+impl Cache {
+    // Uses the given directory, or else $XDG_CACHE_HOME/go-installer (~/.cache/go-installer).
+    pub fn open(dir: Option<PathBuf>) -> Result<Cache> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => match env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+                Some(dir) if dir.is_absolute() => dir.join(CACHE_DIR),
+                _ => match env::var_os("HOME") {
+                    Some(home) if !home.is_empty() => PathBuf::from(home).join(".cache").join(CACHE_DIR),
+                    _ => bail!("HOME is not set; cannot locate the download cache (use --cache-dir)"),
+                },
+            },
+        };
+        Ok(Cache { dir })
+    }
+
+    // Where the tarball for `file` is cached. Creates its entry, so a download can go straight
+    // there (and resume there after an interruption).
+    pub fn tarball_path(&self, file: &GoFile) -> Result<PathBuf> {
+        if file.sha256.len() != 64 || !file.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Invalid sha256 '{}' for {}", file.sha256, file.filename);
+        }
+        if Path::new(&file.filename).file_name().and_then(|n| n.to_str()) != Some(file.filename.as_str()) {
+            bail!("Invalid release file name '{}'", file.filename);
+        }
+        let dir = self.dir.join(file.sha256.to_lowercase());
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create cache entry '{}'", dir.display()))?;
+        serde_json::to_writer_pretty(File::create(dir.join(ENTRY_META))?, file)?;
+        Ok(dir.join(&file.filename))
+    }
+
+    // The newest metadata of a fully downloaded Linux archive of `version`, if one is cached.
+    pub fn find(&self, version: &str, arch: &str) -> Option<GoFile> {
+        self.entries()
+            .ok()?
+            .into_iter()
+            .find(|e| e.path.is_some() && e.file.version == version && e.file.is_linux_archive(arch))
+            .map(|e| e.file)
+    }
+
+    // Marks a cached tarball as used, so pruning keeps it over older ones.
+    pub fn touch(&self, path: &Path) {
+        if let Ok(file) = File::options().append(true).open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+    }
+
+    // Every entry, most recently used first.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(entries);
+        }
+        for dir in fs::read_dir(&self.dir)? {
+            let dir = dir?.path();
+            let Ok(meta) = File::open(dir.join(ENTRY_META)) else {
+                continue;
+            };
+            let Ok(file) = serde_json::from_reader::<_, GoFile>(meta) else {
+                continue;
+            };
+            let tarball = dir.join(&file.filename);
+            let path = tarball.is_file().then_some(tarball);
+            let (size, last_used) = dir_usage(&dir)?;
+            entries.push(CacheEntry { file, path, size, last_used, dir });
+        }
+        entries.sort_by_key(|e| Reverse(e.last_used));
+        Ok(entries)
+    }
+
+    // Removes the least recently used tarballs until the cache fits in `max_size` bytes, never
+    // touching the entry for `keep`. Incomplete downloads are only removed when `partials` is
+    // set, since they can still be resumed. Returns the number of entries and bytes removed.
+    pub fn prune(&self, max_size: u64, keep: Option<&str>, partials: bool) -> Result<(usize, u64)> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+        let (mut removed, mut freed) = (0, 0);
+        entries.reverse();
+        for entry in entries {
+            if keep == Some(entry.file.sha256.as_str()) {
+                continue;
+            }
+            if (entry.path.is_some() && total > max_size) || (entry.path.is_none() && partials) {
+                fs::remove_dir_all(&entry.dir)
+                    .with_context(|| format!("Failed to remove cache entry '{}'", entry.dir.display()))?;
+                total -= entry.size;
+                removed += 1;
+                freed += entry.size;
+            }
+        }
+        Ok((removed, freed))
+    }
+
+    // Removes the whole cache. Returns the number of bytes freed.
+    pub fn clear(&self) -> Result<u64> {
+        if !self.dir.is_dir() {
+            return Ok(0);
+        }
+        let (size, _) = dir_usage(&self.dir)?;
+        fs::remove_dir_all(&self.dir).with_context(|| format!("Failed to remove '{}'", self.dir.display()))?;
+        Ok(size)
+    }
+}
+
+// Total size of the files under `dir` and the newest modification time among them.
+fn dir_usage(dir: &Path) -> Result<(u64, SystemTime)> {
+    let (mut size, mut newest) = (0, SystemTime::UNIX_EPOCH);
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            let (sub_size, sub_newest) = dir_usage(&entry.path())?;
+            size += sub_size;
+            newest = newest.max(sub_newest);
+        } else {
+            size += meta.len();
+            newest = newest.max(meta.modified()?);
+        }
+    }
+    Ok((size, newest))
+}
+
+// Parses a size such as 500M, 2G or 2GiB (binary units) or a plain byte count.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let trimmed = size.trim();
+    let digits = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(digits);
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => bail!("Invalid size '{}' (expected e.g. 500M or 2G)", size),
+    };
+    let number: u64 = number.parse().with_context(|| format!("Invalid size '{}' (expected e.g. 500M or 2G)", size))?;
+    number.checked_mul(1 << shift).with_context(|| format!("Size '{}' is too large", size))
+}
+
+// "3 days ago" and the like, for `cache list`.
+pub fn format_age(time: SystemTime) -> String {
+    let secs = SystemTime::now().duration_since(time).unwrap_or(Duration::ZERO).as_secs();
+    let (value, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{} {}{} ago", value, unit, if value == 1 { "" } else { "s" })
+}
@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const GO_DL_URL: &str = "https://go.dev/dl/";
+const SYSTEM_CONFIG: &str = "/etc/go-installer/config.toml";
+
+// Settings read from config.toml. Every field is optional; command-line flags and environment
+// variables take precedence over the file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub mirror: MirrorConfig,
+    pub cache: CacheConfig,
+    pub download: DownloadConfig,
+    pub network: NetworkConfig,
+    pub auth: AuthConfig,
+    pub signature: SignatureConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct MirrorConfig {
+    // Base URL used for both the release metadata and the tarballs.
+    pub url: Option<String>,
+    // Overrides `url` for the release metadata (the `?mode=json` endpoint).
+    pub metadata_url: Option<String>,
+    // Overrides `url` for the tarballs.
+    pub download_url: Option<String>,
+    // Ordered list of tarball mirrors; overrides `download_url`. The fastest healthy one is used
+    // and the others serve as failovers.
+    pub download_urls: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CacheConfig {
+    // Overrides $XDG_CACHE_HOME/go-installer.
+    pub dir: Option<PathBuf>,
+    // Size the cache is pruned back to after each download, e.g. "2G".
+    pub max_size: Option<String>,
+}
+
+// How tarballs are downloaded.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct DownloadConfig {
+    // Connections used in parallel for one tarball when the server supports range requests.
+    pub connections: Option<usize>,
+    // Bandwidth cap for all downloads together, e.g. "2M" for 2 MiB/s.
+    pub rate_limit: Option<String>,
+    // Timeouts in seconds: establishing a connection, a single read, and a whole download
+    // attempt (no limit by default).
+    pub connect_timeout: Option<f64>,
+    pub read_timeout: Option<f64>,
+    pub timeout: Option<f64>,
+    // A download whose throughput stays near zero for this many seconds is aborted and retried.
+    pub stall_timeout: Option<f64>,
+}
+
+// How to reach the metadata and download URLs from behind a corporate network.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct NetworkConfig {
+    // Proxy for every request; without it HTTPS_PROXY/HTTP_PROXY/ALL_PROXY are used.
+    pub proxy: Option<String>,
+    // Comma-separated hosts and domains reached directly; overrides NO_PROXY.
+    pub no_proxy: Option<String>,
+    // PEM file of extra root certificates to trust, e.g. a private CA.
+    pub ca_bundle: Option<PathBuf>,
+    // PEM client certificate (chain) for mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    // PEM private key for `client_cert`; defaults to the certificate file itself.
+    pub client_key: Option<PathBuf>,
+}
+
+// Credentials for mirrors that require them. Secrets are best kept in the environment or in
+// separate files rather than in the config itself.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    // netrc file to read instead of $NETRC or ~/.netrc.
+    pub netrc: Option<PathBuf>,
+    // File holding a bearer token for the configured mirrors; overrides $GO_INSTALLER_TOKEN.
+    pub token_file: Option<PathBuf>,
+    pub hosts: Vec<HostAuthConfig>,
+}
+
+// Credentials for one host: a token, or a username with a password. Each secret can be given
+// inline, through an environment variable or in a file.
+#[derive(Deserialize, Debug)]
+pub struct HostAuthConfig {
+    pub host: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub password_env: Option<String>,
+    pub password_file: Option<PathBuf>,
+    pub token: Option<String>,
+    pub token_env: Option<String>,
+    pub token_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SignatureConfig {
+    // OpenPGP keyring (armored or binary) trusted instead of the Go signing key, for internal
+    // mirrors that re-sign their archives.
+    pub keyring: Option<PathBuf>,
+}
+
+impl Config {
+    // Reads the given file, or else the first of $XDG_CONFIG_HOME/go-installer/config.toml and
+    // /etc/go-installer/config.toml that exists. No file at all means defaults.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_paths().into_iter().find(|p| p.is_file()) {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read config '{}'", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid config '{}'", path.display()))
+    }
+}
+
+fn default_paths() -> Vec<PathBuf> {
+    let user_config = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+    user_config
+        .map(|dir| dir.join("go-installer/config.toml"))
+        .into_iter()
+        .chain([PathBuf::from(SYSTEM_CONFIG)])
+        .collect()
+}
+
+// Where release metadata and tarballs come from. The checksums always come from the metadata,
+// so tarballs fetched from a mirror are verified against the metadata source.
+#[derive(Debug, Clone)]
+pub struct Sources {
+    pub metadata_url: String,
+    // Tarball base URLs in order of preference; never empty.
+    pub download_urls: Vec<String>,
+}
+
+impl Sources {
+    // Picks each URL from the most specific setting available, in order: the specific override,
+    // then the mirror, first from flags/environment and then from the config file.
+    pub fn resolve(
+        mirror: Option<&str>,
+        metadata_url: Option<&str>,
+        download_urls: &[String],
+        config: &MirrorConfig,
+    ) -> Sources {
+        let fallback = || mirror.or(config.url.as_deref()).unwrap_or(GO_DL_URL).to_string();
+        let metadata_url = metadata_url
+            .or(mirror)
+            .or(config.metadata_url.as_deref())
+            .map(str::to_string)
+            .unwrap_or_else(fallback);
+
+        let download_urls = if !download_urls.is_empty() {
+            download_urls.to_vec()
+        } else if let Some(mirror) = mirror {
+            vec![mirror.to_string()]
+        } else if !config.download_urls.is_empty() {
+            config.download_urls.clone()
+        } else {
+            vec![config.download_url.clone().unwrap_or_else(fallback)]
+        };
+        Sources { metadata_url, download_urls }
+    }
+
+    // The JSON release list. A metadata URL that already carries a query string is used as is,
+    // otherwise it is treated like https://go.dev/dl/.
+    pub fn releases_url(&self, all: bool) -> String {
+        let mut url = if self.metadata_url.contains('?') {
+            self.metadata_url.clone()
+        } else {
+            format!("{}?mode=json", with_trailing_slash(&self.metadata_url))
+        };
+        if all {
+            url.push_str("&include=all");
+        }
+        url
+    }
+
+    pub fn urls_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        std::iter::once(&mut self.metadata_url).chain(&mut self.download_urls)
+    }
+
+    // The URLs set by flags, environment or config, i.e. all but the built-in go.dev default.
+    pub fn configured_urls(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.metadata_url).chain(&self.download_urls).filter(|url| *url != GO_DL_URL)
+    }
+
+    // The tarball's URL on every download mirror, in order of preference.
+    pub fn tarball_urls(&self, filename: &str) -> Vec<String> {
+        self.download_urls.iter().map(|base| format!("{}{}", with_trailing_slash(base), filename)).collect()
+    }
+}
+
+fn with_trailing_slash(url: &str) -> String {
+    if url.ends_with('/') {
+        url.to_string()
+    } else {
+        format!("{}/", url)
+    }
+}
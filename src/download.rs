@@ -0,0 +1,568 @@
+use crate::http::Client;
+use crate::mirror::host;
+use crate::release::GoFile;
+use crate::signature::{Keyring, SignatureCheck};
+use crate::throttle::Throttled;
+use anyhow::{anyhow, bail, Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// Ranges smaller than this are not worth a connection of their own.
+const MIN_CHUNK_SIZE: u64 = 1 << 20;
+
+// Validators saved next to a partial download, so a resume only ever continues the same file.
+#[derive(Serialize, Deserialize, Debug)]
+struct PartialMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    total: u64,
+}
+
+// Downloads a file with a progress bar and returns its SHA-256, computed while the data streams
+// in. Data goes to `<path>.part` first; if a previous run (or a failed attempt) left one behind,
+// the download continues from where it stopped using a Range request, and only then is the
+// partial file read back to hash it. Transient failures, including stalls, are retried
+// according to the client's policy; with several mirror URLs each retry fails over to the next
+// one.
+//
+// With more than one connection configured, large files from servers that support range
+// requests are fetched over several connections at once instead (see `download_parallel`); if
+// that fails, the single-stream download above takes over.
+pub fn download_file(client: &Client, urls: &[String], path: &Path, total_size: u64) -> Result<String> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let connections = client.connections().min((total_size / MIN_CHUNK_SIZE) as usize);
+    // A partial single-stream download is resumed rather than started over.
+    if connections > 1 && !sibling(path, "part.json").is_file() {
+        match download_parallel(client, &urls[0], path, total_size, connections) {
+            Ok(Some(checksum)) => return Ok(checksum),
+            Ok(None) => println!("- {} does not support range requests, using a single connection", host(&urls[0])),
+            Err(err) => println!("- Parallel download failed ({:#}), using a single connection", err),
+        }
+    }
+    let mut attempt = 0;
+    client.retry(&format!("Download of {}", name), || {
+        let url = &urls[attempt % urls.len()];
+        if attempt > 0 && urls.len() > 1 {
+            println!("- Failing over to mirror {}", host(url));
+        }
+        attempt += 1;
+        download_once(client, url, path, total_size)
+    })
+}
+
+fn download_once(client: &Client, url: &str, path: &Path, total_size: u64) -> Result<String> {
+    let part_path = sibling(path, "part");
+    let meta_path = sibling(path, "part.json");
+
+    let resume = read_partial(&part_path, &meta_path, total_size);
+    let mut request = client.download(url);
+    if let Some((offset, meta)) = &resume {
+        request = request.set("Range", &format!("bytes={}-", offset));
+        // If-Range makes the server send the whole file instead if it changed in the meantime.
+        if let Some(validator) = meta.etag.as_ref().or(meta.last_modified.as_ref()) {
+            request = request.set("If-Range", validator);
+        }
+    }
+
+    let res = match request.call() {
+        // The partial file already holds everything.
+        Err(ureq::Error::Status(416, _)) if resume.as_ref().is_some_and(|(offset, m)| *offset == m.total) => {
+            let checksum = file_checksum(&part_path)?;
+            finish_partial(&part_path, &meta_path, path)?;
+            return Ok(checksum);
+        }
+        Err(ureq::Error::Status(416, _)) => {
+            discard_partial(&part_path, &meta_path);
+            return download_once(client, url, path, total_size);
+        }
+        res => res?,
+    };
+
+    let offset = match (&resume, res.status()) {
+        (Some((offset, meta)), 206) if content_range_matches(&res, *offset, meta.total) => *offset,
+        (Some(_), 206) => {
+            discard_partial(&part_path, &meta_path);
+            bail!("Server returned an unexpected Content-Range for '{}'", url);
+        }
+        (Some(_), _) => {
+            println!("- Cannot resume (server ignored the range or the file changed), downloading from scratch...");
+            0
+        }
+        (None, _) => 0,
+    };
+
+    let total = match res.header("Content-Length").and_then(|l| l.parse::<u64>().ok()) {
+        Some(length) => offset + length,
+        None => total_size,
+    };
+    if total != total_size {
+        bail!("Server reports {} bytes for '{}', expected {}", total, url, total_size);
+    }
+
+    let mut hasher = Sha256::new();
+    let mut file = if offset > 0 {
+        println!("- Resuming download at {} of {} bytes...", offset, total);
+        io::copy(&mut File::open(&part_path)?, &mut hasher)?;
+        OpenOptions::new().append(true).open(&part_path)?
+    } else {
+        let meta = PartialMeta {
+            etag: res.header("ETag").map(str::to_string),
+            last_modified: res.header("Last-Modified").map(str::to_string),
+            total,
+        };
+        serde_json::to_writer(File::create(&meta_path)?, &meta)?;
+        File::create(&part_path)?
+    };
+
+    let pb = progress_bar(client, total_size, &path.file_name().unwrap().to_string_lossy(), url, 1)?;
+    pb.set_position(offset);
+
+    let mut writer = HashingWriter { file: &mut file, hasher: &mut hasher };
+    io::copy(&mut pb.wrap_read(client.throttle().reader(res.into_reader())), &mut writer)
+        .with_context(|| format!("Download of '{}' was interrupted; run again to resume", url))?;
+    if file.metadata()?.len() != total {
+        let msg = format!("Download of '{}' ended early; run again to resume", url);
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+    }
+
+    let checksum = format!("{:x}", hasher.finalize());
+    pb.finish_with_message(format!("Download complete (sha256 {}).", checksum));
+    finish_partial(&part_path, &meta_path, path)?;
+    Ok(checksum)
+}
+
+// Fetches the file as `connections` byte ranges at once, each written into place in
+// `<path>.part`, with one progress bar for all of them. Returns None, without downloading
+// anything, when the server does not advertise range support. The ranges arrive out of order,
+// so the file is hashed once it is complete; a failed parallel download is discarded rather
+// than resumed.
+fn download_parallel(
+    client: &Client,
+    url: &str,
+    path: &Path,
+    total_size: u64,
+    connections: usize,
+) -> Result<Option<String>> {
+    let res = client.retry(&format!("Request to {}", url), || Ok(client.head(url).call()?))?;
+    if !res.header("Accept-Ranges").is_some_and(|v| v.trim().eq_ignore_ascii_case("bytes")) {
+        return Ok(None);
+    }
+    if let Some(length) = res.header("Content-Length").and_then(|l| l.parse::<u64>().ok()) {
+        if length != total_size {
+            bail!("Server reports {} bytes for '{}', expected {}", length, url, total_size);
+        }
+    }
+    // Every range must come from the same version of the file.
+    let validator = res.header("ETag").or(res.header("Last-Modified")).map(str::to_string);
+
+    let part_path = sibling(path, "part");
+    let file = File::create(&part_path)?;
+    file.set_len(total_size)?;
+    let name = path.file_name().unwrap().to_string_lossy();
+    let pb = progress_bar(client, total_size, &name, url, connections)?;
+
+    let chunk = total_size.div_ceil(connections as u64);
+    let failed = AtomicBool::new(false);
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..connections as u64)
+            .map(|i| {
+                let range = Range { start: i * chunk, end: ((i + 1) * chunk).min(total_size) - 1, total: total_size };
+                let (file, pb, validator, failed) = (&file, &pb, validator.as_deref(), &failed);
+                scope.spawn(move || {
+                    let result = download_range(client, url, file, range, validator, pb, failed);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    result
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap_or_else(|_| Err(anyhow!("Download thread panicked")))).collect()
+    });
+    if let Some(err) = results.into_iter().find_map(Result::err) {
+        pb.abandon();
+        let _ = fs::remove_file(&part_path);
+        return Err(err);
+    }
+
+    pb.finish_with_message("Download complete.");
+    let checksum = file_checksum(&part_path)?;
+    finish_partial(&part_path, &sibling(path, "part.json"), path)?;
+    Ok(Some(checksum))
+}
+
+// An inclusive byte range of a file of `total` bytes.
+#[derive(Clone, Copy)]
+struct Range {
+    start: u64,
+    end: u64,
+    total: u64,
+}
+
+// Downloads one range into its place in `file`, retrying from where it stopped. Gives up early,
+// without an error of its own, once another range has failed.
+fn download_range(
+    client: &Client,
+    url: &str,
+    file: &File,
+    range: Range,
+    validator: Option<&str>,
+    pb: &ProgressBar,
+    failed: &AtomicBool,
+) -> Result<()> {
+    let mut offset = range.start;
+    client.retry(&format!("Download of bytes {}-{} of '{}'", range.start, range.end, url), || {
+        let mut request = client.download(url).set("Range", &format!("bytes={}-{}", offset, range.end));
+        if let Some(validator) = validator {
+            request = request.set("If-Range", validator);
+        }
+        let res = request.call()?;
+        if res.status() != 206 || !content_range_matches(&res, offset, range.total) {
+            bail!("Server ignored the range request or the file changed ('{}')", url);
+        }
+
+        let mut body = client.throttle().reader(res.into_reader().take(range.end + 1 - offset));
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            if failed.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let n = body.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all_at(&buf[..n], offset)?;
+            offset += n as u64;
+            pb.inc(n as u64);
+        }
+        if offset <= range.end {
+            let msg = format!("Download of '{}' ended early", url);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+        }
+        Ok(())
+    })
+}
+
+// Streams a file from the mirrors into `consume` without ever writing it to disk. The data is
+// hashed, and checked against its signature, as it passes through; the reader handed to
+// `consume` only reports the end of the data once the whole file arrived and matched, and fails
+// otherwise, so a consumer that reads to the end succeeds only on verified data. A failed
+// attempt starts over from the beginning, on the next mirror if there are several.
+pub fn stream_file<T>(
+    client: &Client,
+    urls: &[String],
+    file: &GoFile,
+    checksum_source: &str,
+    signature: Option<(&Keyring, &Path)>,
+    mut consume: impl FnMut(&mut dyn Read) -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 0;
+    client.retry(&format!("Download of {}", file.filename), || {
+        let url = &urls[attempt % urls.len()];
+        if attempt > 0 && urls.len() > 1 {
+            println!("- Failing over to mirror {}", host(url));
+        }
+        attempt += 1;
+        let res = client.download(url).call()?;
+        if let Some(length) = res.header("Content-Length").and_then(|l| l.parse::<u64>().ok()) {
+            if length != file.size {
+                bail!("Server reports {} bytes for '{}', expected {}", length, url, file.size);
+            }
+        }
+        let mut reader = VerifyingReader {
+            inner: client.throttle().reader(res.into_reader()),
+            pb: progress_bar(client, file.size, &file.filename, url, 1)?,
+            hasher: Sha256::new(),
+            read: 0,
+            file,
+            checksum_source,
+            signature: signature.map(|(keyring, signature)| keyring.start(signature)).transpose()?,
+            verified: false,
+        };
+        consume(&mut reader)
+    })
+}
+
+// The reader behind `stream_file`.
+struct VerifyingReader<'a> {
+    inner: Throttled<'a, Box<dyn Read + Send + Sync>>,
+    pb: ProgressBar,
+    hasher: Sha256,
+    read: u64,
+    file: &'a GoFile,
+    checksum_source: &'a str,
+    signature: Option<SignatureCheck>,
+    verified: bool,
+}
+
+impl VerifyingReader<'_> {
+    fn verify(&mut self) -> io::Result<()> {
+        if self.read < self.file.size {
+            let msg = format!("Download of {} ended early", self.file.filename);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+        }
+        self.pb.finish_with_message("Download complete.");
+        let invalid = |err: anyhow::Error| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", err));
+        compare_checksum(&self.file.sha256, &format!("{:x}", self.hasher.finalize_reset())).map_err(invalid)?;
+        println!("✔ Checksum Verified (against {})", self.checksum_source);
+        if let Some(check) = self.signature.take() {
+            let key = check.finish(Path::new(&self.file.filename)).map_err(invalid)?;
+            println!("✔ Signature Verified (key {})", key);
+        }
+        Ok(())
+    }
+}
+
+impl Read for VerifyingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !self.verified {
+            self.verify()?;
+            self.verified = true;
+        }
+        self.hasher.update(&buf[..n]);
+        self.read += n as u64;
+        self.pb.inc(n as u64);
+        if let Some(check) = self.signature.as_mut() {
+            check.write_all(&buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+// Copies the downloaded data into the file and the hasher in the same pass.
+struct HashingWriter<'a> {
+    file: &'a mut File,
+    hasher: &'a mut Sha256,
+}
+
+impl Write for HashingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Verifies the SHA256 checksum using the expected hash from the API.
+pub fn verify_checksum(expected_checksum: &str, file_path: &Path) -> Result<()> {
+    compare_checksum(expected_checksum, &file_checksum(file_path)?)
+}
+
+// Compares a checksum computed during a download with the expected one.
+pub fn compare_checksum(expected_checksum: &str, calculated_checksum: &str) -> Result<()> {
+    if calculated_checksum != expected_checksum {
+        bail!(
+            "Checksum mismatch!\n  Expected:   {}\n  Calculated: {}",
+            expected_checksum, calculated_checksum
+        );
+    }
+    Ok(())
+}
+
+// A progress bar whose message names the download and the limits it runs under.
+fn progress_bar(client: &Client, total_size: u64, name: &str, url: &str, connections: usize) -> Result<ProgressBar> {
+    let pb = ProgressBar::new(total_size);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")?
+        .progress_chars("=>-"));
+    let connections = if connections > 1 { format!("{} connections, ", connections) } else { String::new() };
+    pb.set_message(format!("Downloading {} from {} ({}{})", name, host(url), connections, client.describe_limits()));
+    Ok(pb)
+}
+
+fn file_checksum(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Reads an expected checksum given either as the hex digest itself or as the path to a
+// `.sha256` file (a bare digest or `sha256sum` output).
+pub fn parse_checksum(value: &str) -> Result<String> {
+    let is_digest = |s: &str| s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if is_digest(value) {
+        return Ok(value.to_ascii_lowercase());
+    }
+    let contents = fs::read_to_string(value)
+        .with_context(|| format!("'{}' is neither a sha256 digest nor a readable checksum file", value))?;
+    match contents.split_whitespace().next() {
+        Some(digest) if is_digest(digest) => Ok(digest.to_ascii_lowercase()),
+        _ => bail!("No sha256 digest found in '{}'", value),
+    }
+}
+
+// The resume offset and saved validators, if a usable partial download exists.
+fn read_partial(part_path: &Path, meta_path: &Path, total_size: u64) -> Option<(u64, PartialMeta)> {
+    let offset = fs::metadata(part_path).ok()?.len();
+    let meta: PartialMeta = serde_json::from_reader(File::open(meta_path).ok()?).ok()?;
+    if offset == 0 || offset > meta.total || meta.total != total_size {
+        discard_partial(part_path, meta_path);
+        return None;
+    }
+    Some((offset, meta))
+}
+
+// Checks "Content-Range: bytes <offset>-<end>/<total>" against what we asked for.
+fn content_range_matches(res: &ureq::Response, offset: u64, total: u64) -> bool {
+    let Some(range) = res.header("Content-Range").and_then(|r| r.strip_prefix("bytes ")) else {
+        return false;
+    };
+    let Some((span, length)) = range.split_once('/') else {
+        return false;
+    };
+    let start = span.split('-').next().and_then(|s| s.parse::<u64>().ok());
+    start == Some(offset) && (length == "*" || length.parse::<u64>().ok() == Some(total))
+}
+
+fn finish_partial(part_path: &Path, meta_path: &Path, path: &Path) -> Result<()> {
+    fs::rename(part_path, path).with_context(|| format!("Failed to move download to '{}'", path.display()))?;
+    let _ = fs::remove_file(meta_path);
+    Ok(())
+}
+
+fn discard_partial(part_path: &Path, meta_path: &Path) {
+    let _ = fs::remove_file(part_path);
+    let _ = fs::remove_file(meta_path);
+}
+
+// "<path>.<suffix>", e.g. go1.22.5.linux-amd64.tar.gz.part
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stand_in_server;
+    use crate::tempdir::PrivateDir;
+    use std::sync::{Arc, Mutex};
+    use tiny_http::{Header, Response};
+
+    const ETAG: &str = "\"v2\"";
+
+    fn body() -> Vec<u8> {
+        (0..5000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    // The Range and If-Range headers of each request the stand-in server received.
+    type Seen = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+    // A stand-in for a download server holding body() with ETag ETAG. "bytes=<start>-" ranges are
+    // honored if `ranges` is set and If-Range, when sent, matches; past the end they get a 416.
+    fn upstream(ranges: bool) -> (String, Seen) {
+        let seen = Seen::default();
+        let log = seen.clone();
+        let body = body();
+        let url = stand_in_server(move |request| {
+            let get = |name: &'static str| {
+                request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.to_string())
+            };
+            let (range, if_range) = (get("Range"), get("If-Range"));
+            log.lock().unwrap().push((range.clone(), if_range.clone()));
+
+            let fresh = if_range.as_deref().is_none_or(|validator| validator == ETAG);
+            let start = range
+                .filter(|_| ranges && fresh)
+                .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            let response = match start {
+                Some(start) if start >= body.len() => Response::from_data(Vec::new())
+                    .with_status_code(416)
+                    .with_header(header("Content-Range", &format!("bytes */{}", body.len()))),
+                Some(start) => {
+                    let content_range = format!("bytes {}-{}/{}", start, body.len() - 1, body.len());
+                    Response::from_data(body[start..].to_vec())
+                        .with_status_code(206)
+                        .with_header(header("Content-Range", &content_range))
+                }
+                None => Response::from_data(body.clone()),
+            };
+            let _ = request.respond(response.with_header(header("ETag", ETAG)));
+        });
+        (format!("{}/go.tar.gz", url), seen)
+    }
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::from_bytes(name, value).unwrap()
+    }
+
+    // A download target with a partial download of `part` left behind under `etag`.
+    fn partial(part: &[u8], etag: &str) -> (PrivateDir, PathBuf) {
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let path = dir.join("go.tar.gz");
+        fs::write(sibling(&path, "part"), part).unwrap();
+        let meta = PartialMeta { etag: Some(etag.to_string()), last_modified: None, total: body().len() as u64 };
+        serde_json::to_writer(File::create(sibling(&path, "part.json")).unwrap(), &meta).unwrap();
+        (dir, path)
+    }
+
+    fn download(url: &str, path: &Path) -> String {
+        let checksum = download_file(&Client::for_tests(), &[url.to_string()], path, body().len() as u64).unwrap();
+        assert_eq!(fs::read(path).unwrap(), body());
+        assert!(!sibling(path, "part").exists() && !sibling(path, "part.json").exists());
+        checksum
+    }
+
+    fn requests(seen: &Seen) -> Vec<(Option<String>, Option<String>)> {
+        seen.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn downloads_and_hashes() {
+        let (url, seen) = upstream(true);
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        assert_eq!(download(&url, &dir.join("go.tar.gz")), sha256(&body()));
+        assert_eq!(requests(&seen), vec![(None, None)]);
+    }
+
+    #[test]
+    fn resumes_with_a_range_request() {
+        let (url, seen) = upstream(true);
+        let (_dir, path) = partial(&body()[..1200], ETAG);
+        assert_eq!(download(&url, &path), sha256(&body()));
+        assert_eq!(requests(&seen), vec![(Some("bytes=1200-".to_string()), Some(ETAG.to_string()))]);
+    }
+
+    #[test]
+    fn starts_over_when_the_range_is_ignored() {
+        let (url, seen) = upstream(false);
+        let (_dir, path) = partial(&body()[..1200], ETAG);
+        assert_eq!(download(&url, &path), sha256(&body()));
+        assert_eq!(requests(&seen).len(), 1);
+    }
+
+    #[test]
+    fn finishes_a_complete_partial_download_on_416() {
+        let (url, seen) = upstream(true);
+        let (_dir, path) = partial(&body(), ETAG);
+        assert_eq!(download(&url, &path), sha256(&body()));
+        assert_eq!(requests(&seen), vec![(Some("bytes=5000-".to_string()), Some(ETAG.to_string()))]);
+    }
+
+    #[test]
+    fn starts_over_when_the_file_changed() {
+        let (url, seen) = upstream(true);
+        // The leftover bytes come from an older file, which If-Range must keep out of the result.
+        let (_dir, path) = partial(&[0xff; 1200], "\"v1\"");
+        assert_eq!(download(&url, &path), sha256(&body()));
+        assert_eq!(requests(&seen), vec![(Some("bytes=1200-".to_string()), Some("\"v1\"".to_string()))]);
+    }
+}
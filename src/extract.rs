@@ -0,0 +1,175 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::EntryType;
+
+// Every entry of a Go release archive lives under this directory.
+const ARCHIVE_ROOT: &str = "go";
+
+// Extracts a Go tarball into `dest`, refusing anything a genuine release would not contain:
+// entries outside go/, absolute paths, `..` components, device nodes and FIFOs, and links
+// pointing outside the tree. Archives from mirrors are not trusted any more than go.dev's.
+// The archive is always read to its very end, so a reader that verifies the data when it is
+// exhausted gets the final say.
+pub fn unpack(tar_gz: &mut dyn Read, dest: &Path) -> Result<()> {
+    let mut gz = flate2::read::GzDecoder::new(&mut *tar_gz);
+    let mut archive = tar::Archive::new(&mut gz);
+    fs::create_dir_all(dest)?;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let reject = |reason: &str| -> Result<()> {
+            bail!("Refusing to extract unsafe archive entry '{}': {}", path.display(), reason)
+        };
+
+        if path.components().any(|c| c == Component::ParentDir) {
+            return reject("contains a '..' component");
+        }
+        let Some(normalized) = normalize(&path) else {
+            return reject("absolute path");
+        };
+        if !normalized.starts_with(ARCHIVE_ROOT) {
+            return reject("outside the go/ directory");
+        }
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            EntryType::Symlink => {
+                let target = entry.link_name()?.context("Symlink entry without a target")?;
+                let parent = normalized.parent().unwrap_or(Path::new(""));
+                let resolved = if target.is_absolute() { None } else { normalize(&parent.join(&target)) };
+                if !resolved.is_some_and(|r| r.starts_with(ARCHIVE_ROOT)) {
+                    return reject(&format!("symlink to '{}' escapes the go/ directory", target.display()));
+                }
+            }
+            EntryType::Link => {
+                let target = entry.link_name()?.context("Hard link entry without a target")?;
+                if !normalize(&target).is_some_and(|t| t.starts_with(ARCHIVE_ROOT)) {
+                    return reject(&format!("hard link to '{}' escapes the go/ directory", target.display()));
+                }
+            }
+            // PAX global headers carry metadata only.
+            EntryType::XGlobalHeader => continue,
+            other => return reject(&format!("unsupported entry type {:?}", other)),
+        }
+
+        if !entry.unpack_in(dest)? {
+            return reject("resolves outside the destination");
+        }
+    }
+    // Tar padding, the gzip trailer and anything after it.
+    io::copy(&mut gz, &mut io::sink())?;
+    io::copy(tar_gz, &mut io::sink())?;
+    Ok(())
+}
+
+// Lexically resolves `.` and `..` in a relative path. Returns None for absolute paths and for
+// paths that climb above their starting point.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::PrivateDir;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Cursor;
+
+    // A gzipped tarball of empty entries (regular files hold a few bytes), given as
+    // (path, type, link target).
+    fn archive(entries: &[(&str, EntryType, Option<&str>)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, kind, link) in entries {
+            let mut header = tar::Header::new_gnu();
+            // Written raw, since set_path refuses the very paths these tests need.
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_mode(0o644);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            let data: &[u8] = if *kind == EntryType::Regular { b"data" } else { b"" };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn unpack_archive(entries: &[(&str, EntryType, Option<&str>)]) -> (PrivateDir, Result<()>) {
+        let dest = PrivateDir::new("go-installer-test").unwrap();
+        let result = unpack(&mut Cursor::new(archive(entries)), &dest.join("root"));
+        (dest, result)
+    }
+
+    fn assert_rejected(entries: &[(&str, EntryType, Option<&str>)], entry: &str, reason: &str) {
+        let (_dest, result) = unpack_archive(entries);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains(&format!("'{}'", entry)), "{}", err);
+        assert!(err.contains(reason), "{}", err);
+    }
+
+    #[test]
+    fn unpacks_a_go_tree() {
+        let (dest, result) = unpack_archive(&[
+            ("go/", EntryType::Directory, None),
+            ("go/bin/go", EntryType::Regular, None),
+            ("go/bin/gofmt", EntryType::Link, Some("go/bin/go")),
+            ("go/pkg/tool/go", EntryType::Symlink, Some("../../bin/go")),
+        ]);
+        result.unwrap();
+        assert_eq!(fs::read(dest.join("root/go/bin/gofmt")).unwrap(), b"data");
+        assert_eq!(fs::read(dest.join("root/go/pkg/tool/go")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert_rejected(&[("/etc/passwd", EntryType::Regular, None)], "/etc/passwd", "absolute path");
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        let entries = [("go/../../etc/passwd", EntryType::Regular, None)];
+        assert_rejected(&entries, "go/../../etc/passwd", "'..' component");
+    }
+
+    #[test]
+    fn rejects_entries_outside_go() {
+        let entries = [("go/bin/go", EntryType::Regular, None), ("etc/passwd", EntryType::Regular, None)];
+        assert_rejected(&entries, "etc/passwd", "outside the go/ directory");
+    }
+
+    #[test]
+    fn rejects_escaping_links() {
+        let entries = [("go/etc", EntryType::Symlink, Some("../../etc"))];
+        assert_rejected(&entries, "go/etc", "symlink to '../../etc' escapes");
+        let entries = [("go/etc", EntryType::Symlink, Some("/etc"))];
+        assert_rejected(&entries, "go/etc", "symlink to '/etc' escapes");
+        let entries = [("go/passwd", EntryType::Link, Some("etc/passwd"))];
+        assert_rejected(&entries, "go/passwd", "hard link to 'etc/passwd' escapes");
+    }
+
+    #[test]
+    fn rejects_devices_and_fifos() {
+        assert_rejected(&[("go/null", EntryType::Char, None)], "go/null", "unsupported entry type Char");
+        assert_rejected(&[("go/disk", EntryType::Block, None)], "go/disk", "unsupported entry type Block");
+        assert_rejected(&[("go/fifo", EntryType::Fifo, None)], "go/fifo", "unsupported entry type Fifo");
+    }
+}
@@ -0,0 +1,125 @@
+use crate::version::{GoVersion, VersionReq};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The `go` and `toolchain` directives of a go.mod or go.work file.
+#[derive(Debug, Default)]
+struct Directives {
+    go: Option<String>,
+    toolchain: Option<String>,
+}
+
+// Finds the project file governing `dir` and turns its directives into a version requirement.
+// Like the go command, a go.work found in `dir` or any parent wins over go.mod.
+pub fn find_project_version(dir: &Path) -> Result<(PathBuf, VersionReq)> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Cannot access project directory '{}'", dir.display()))?;
+
+    let path = ["go.work", "go.mod"]
+        .iter()
+        .find_map(|name| dir.ancestors().map(|d| d.join(name)).find(|p| p.is_file()))
+        .with_context(|| format!("No go.work or go.mod found in '{}' or its parents", dir.display()))?;
+
+    let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let req = version_req(&parse_directives(&contents))
+        .with_context(|| format!("Invalid Go version in '{}'", path.display()))?;
+    Ok((path, req))
+}
+
+fn parse_directives(contents: &str) -> Directives {
+    let mut directives = Directives::default();
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("go"), Some(v)) => directives.go = Some(v.to_string()),
+            (Some("toolchain"), Some(v)) => directives.toolchain = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    directives
+}
+
+// The toolchain line names an exact release and is honored when it is at least the `go` version.
+// A `go` line with a patch ("1.22.5") is itself an exact release; without one ("1.22") any
+// patch of that minor line satisfies it, so the newest is picked.
+fn version_req(directives: &Directives) -> Result<VersionReq> {
+    let go = match &directives.go {
+        Some(go) => go,
+        None => bail!("missing 'go' directive"),
+    };
+    let go_req = if go.matches('.').count() >= 2 || go.contains("rc") || go.contains("beta") {
+        VersionReq::Exact(GoVersion::parse(go)?)
+    } else {
+        VersionReq::parse(&format!("~{}", go))?
+    };
+
+    if let Some(toolchain) = directives.toolchain.as_deref().filter(|t| *t != "default") {
+        // Custom toolchains may carry a suffix, e.g. "go1.22.5-corp".
+        let toolchain = GoVersion::parse(toolchain.split(['-', '+']).next().unwrap_or(toolchain))?;
+        let go_floor = GoVersion::parse(go)?;
+        if toolchain >= go_floor {
+            return Ok(VersionReq::Exact(toolchain));
+        }
+    }
+    Ok(go_req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::PrivateDir;
+
+    // The requirement a go.mod with these contents resolves to, as displayed.
+    fn req(contents: &str) -> String {
+        version_req(&parse_directives(contents)).unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_directives() {
+        let directives = parse_directives("module example.com/m // go 1.10\ngo 1.22 // minimum\ntoolchain go1.22.5\n");
+        assert_eq!(directives.go.as_deref(), Some("1.22"));
+        assert_eq!(directives.toolchain.as_deref(), Some("go1.22.5"));
+        assert!(parse_directives("module m\n").go.is_none());
+    }
+
+    #[test]
+    fn maps_the_go_directive() {
+        assert_eq!(req("go 1.22"), "~1.22");
+        assert_eq!(req("go 1.22.5"), "go1.22.5");
+        assert_eq!(req("go 1.23rc1"), "go1.23rc1");
+        assert!(version_req(&parse_directives("module m\n")).is_err());
+        assert!(version_req(&parse_directives("go one.two\n")).is_err());
+    }
+
+    #[test]
+    fn prefers_a_newer_toolchain() {
+        assert_eq!(req("go 1.22\ntoolchain go1.22.5"), "go1.22.5");
+        assert_eq!(req("go 1.21.0\ntoolchain go1.22.1"), "go1.22.1");
+        // An older toolchain than the go line cannot build the module.
+        assert_eq!(req("go 1.22.5\ntoolchain go1.21.13"), "go1.22.5");
+        assert_eq!(req("go 1.22\ntoolchain default"), "~1.22");
+        assert_eq!(req("go 1.22\ntoolchain go1.22.5-corp.2"), "go1.22.5");
+        assert_eq!(req("go 1.22\ntoolchain go1.22.5+auto"), "go1.22.5");
+    }
+
+    #[test]
+    fn prefers_go_work_over_go_mod() {
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let module = dir.join("module");
+        fs::create_dir(&module).unwrap();
+        fs::write(module.join("go.mod"), "module m\n\ngo 1.21.3\n").unwrap();
+        fs::write(dir.join("go.work"), "go 1.22\n\nuse ./module\n").unwrap();
+
+        let (path, req) = find_project_version(&module).unwrap();
+        assert_eq!(path, dir.path().canonicalize().unwrap().join("go.work"));
+        assert_eq!(req.to_string(), "~1.22");
+
+        fs::remove_file(dir.join("go.work")).unwrap();
+        let (path, req) = find_project_version(&module).unwrap();
+        assert_eq!(path.file_name().unwrap(), "go.mod");
+        assert_eq!(req.to_string(), "go1.21.3");
+    }
+}
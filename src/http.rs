@@ -0,0 +1,254 @@
+use crate::auth::Auth;
+use crate::cache::parse_size;
+use crate::config::{DownloadConfig, NetworkConfig};
+use crate::retry::RetryPolicy;
+use crate::throttle::Throttle;
+use anyhow::{bail, Context, Result};
+use rustls::RootCertStore;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use serde::de::DeserializeOwned;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// A connection that receives nothing for this long is given up.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_CONNECTIONS: usize = 1;
+
+// The HTTP client shared by the release API lookups and the downloader.
+#[derive(Debug, Clone)]
+pub struct Client {
+    direct: ureq::Agent,
+    // Agents going through the proxy configured for each scheme, if any.
+    https_proxy: Option<ureq::Agent>,
+    http_proxy: Option<ureq::Agent>,
+    no_proxy: Vec<String>,
+    auth: Auth,
+    retry: RetryPolicy,
+    connections: usize,
+    // Limit for each download attempt as a whole.
+    timeout: Option<Duration>,
+    throttle: Arc<Throttle>,
+}
+
+impl Client {
+    pub fn new(retry: RetryPolicy, network: &NetworkConfig, download: &DownloadConfig, auth: Auth) -> Result<Self> {
+        let connections = download.connections.unwrap_or(DEFAULT_CONNECTIONS);
+        if connections == 0 {
+            bail!("The number of download connections must be at least 1");
+        }
+        let connect_timeout = seconds("connect timeout", download.connect_timeout)?.unwrap_or(CONNECT_TIMEOUT);
+        let read_timeout = seconds("read timeout", download.read_timeout)?.unwrap_or(READ_TIMEOUT);
+        let stall_timeout = seconds("stall timeout", download.stall_timeout)?.unwrap_or(STALL_TIMEOUT);
+        let rate_limit = match &download.rate_limit {
+            Some(rate) => Some(parse_size(rate.trim_end_matches("/s")).context("Invalid rate limit")?),
+            None => None,
+        };
+        if rate_limit == Some(0) {
+            bail!("The rate limit must be above zero");
+        }
+
+        let tls = tls_config(network)?;
+        let agent = |proxy: Option<&str>| -> Result<ureq::Agent> {
+            let mut builder = ureq::AgentBuilder::new()
+                .timeout_connect(connect_timeout)
+                // A connection that goes completely silent never returns from a read, so the
+                // throttle cannot see the stall; the read timeout has to catch it in time.
+                .timeout_read(read_timeout.min(stall_timeout))
+                // Credentials survive a redirect only within the same host.
+                .redirect_auth_headers(ureq::RedirectAuthHeaders::SameHost);
+            if let Some(tls) = &tls {
+                builder = builder.tls_config(tls.clone());
+            }
+            if let Some(proxy) = proxy {
+                builder = builder.proxy(ureq::Proxy::new(proxy).context("Invalid proxy URL")?);
+            }
+            Ok(builder.build())
+        };
+
+        // An explicit proxy applies to both schemes; otherwise the usual environment variables.
+        let https_proxy = network.proxy.clone().or_else(|| from_env(&["HTTPS_PROXY", "https_proxy"]));
+        let http_proxy = network.proxy.clone().or_else(|| from_env(&["HTTP_PROXY", "http_proxy"]));
+        let no_proxy = network.no_proxy.clone().or_else(|| from_env(&["NO_PROXY", "no_proxy"])).unwrap_or_default();
+
+        Ok(Client {
+            direct: agent(None)?,
+            https_proxy: https_proxy.as_deref().map(|p| agent(Some(p))).transpose()?,
+            http_proxy: http_proxy.as_deref().map(|p| agent(Some(p))).transpose()?,
+            no_proxy: no_proxy.split(',').map(no_proxy_entry).filter(|entry| !entry.is_empty()).collect(),
+            auth,
+            retry,
+            connections,
+            timeout: seconds("timeout", download.timeout)?,
+            throttle: Arc::new(Throttle::new(rate_limit, stall_timeout)),
+        })
+    }
+
+    // A GET for downloading a file, limited by the overall download timeout.
+    pub fn download(&self, url: &str) -> ureq::Request {
+        match self.timeout {
+            Some(timeout) => self.get(url).timeout(timeout),
+            None => self.get(url),
+        }
+    }
+
+    pub fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    // The download limits, for progress bar messages.
+    pub fn describe_limits(&self) -> String {
+        match self.timeout {
+            Some(timeout) => format!("{}, timeout {}s", self.throttle.describe(), timeout.as_secs_f64()),
+            None => self.throttle.describe(),
+        }
+    }
+
+    // How many connections a single download may use.
+    pub fn connections(&self) -> usize {
+        self.connections
+    }
+
+    pub fn get(&self, url: &str) -> ureq::Request {
+        self.authorize(url, self.agent(url).get(url))
+    }
+
+    pub fn head(&self, url: &str) -> ureq::Request {
+        self.authorize(url, self.agent(url).head(url))
+    }
+
+    fn authorize(&self, url: &str, request: ureq::Request) -> ureq::Request {
+        match self.auth.header(url) {
+            Some(header) => request.set("Authorization", &header),
+            None => request,
+        }
+    }
+
+    // Fetches and decodes a JSON document, retrying transient failures.
+    pub fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.retry(&format!("Request to {}", url), || Ok(self.get(url).call()?.into_json()?))
+    }
+
+    pub fn retry<T>(&self, what: &str, op: impl FnMut() -> Result<T>) -> Result<T> {
+        self.retry.run(what, op)
+    }
+
+    // The proxied agent for the URL's scheme, unless NO_PROXY lists its host.
+    fn agent(&self, url: &str) -> &ureq::Agent {
+        let proxied = if url.starts_with("https://") { &self.https_proxy } else { &self.http_proxy };
+        match proxied {
+            Some(agent) if !self.bypasses_proxy(url) => agent,
+            _ => &self.direct,
+        }
+    }
+
+    // NO_PROXY entries match the host itself and its subdomains; "*" matches everything.
+    fn bypasses_proxy(&self, url: &str) -> bool {
+        let host = url_host(url).to_ascii_lowercase();
+        self.no_proxy.iter().any(|entry| {
+            entry == "*" || host == *entry || host.strip_suffix(entry.as_str()).is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+}
+
+#[cfg(test)]
+impl Client {
+    // A client for tests against local stand-in servers: no retries, and never a proxy.
+    pub fn for_tests() -> Client {
+        let network = NetworkConfig { no_proxy: Some("*".to_string()), ..NetworkConfig::default() };
+        let retry = RetryPolicy::new(0, Duration::ZERO);
+        Client::new(retry, &network, &DownloadConfig::default(), Auth::default()).unwrap()
+    }
+}
+
+// Answers requests on a local port with `handle`, one at a time, until the test process exits.
+// Returns the server's base URL, e.g. "http://127.0.0.1:41234".
+#[cfg(test)]
+pub fn stand_in_server(mut handle: impl FnMut(tiny_http::Request) + Send + 'static) -> String {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+    std::thread::spawn(move || server.incoming_requests().for_each(&mut handle));
+    url
+}
+
+// A timeout given in seconds.
+fn seconds(name: &str, value: Option<f64>) -> Result<Option<Duration>> {
+    match value {
+        Some(secs) if secs > 0.0 => {
+            Ok(Some(Duration::try_from_secs_f64(secs).with_context(|| format!("Invalid {}", name))?))
+        }
+        Some(_) => bail!("The {} must be above zero", name),
+        None => Ok(None),
+    }
+}
+
+// The first non-empty variable of `names`, falling back to ALL_PROXY.
+fn from_env(names: &[&str]) -> Option<String> {
+    names.iter().chain(&["ALL_PROXY", "all_proxy"]).find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()))
+}
+
+// Normalizes a NO_PROXY entry to a bare lowercase domain: "*.corp.example:443" -> "corp.example".
+fn no_proxy_entry(entry: &str) -> String {
+    let entry = entry.trim().trim_start_matches("*.").trim_start_matches('.');
+    let entry = match entry.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => entry,
+    };
+    entry.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase()
+}
+
+// The host of a URL, without userinfo, port or IPv6 brackets.
+pub fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    }
+}
+
+// A rustls configuration with the extra CA bundle and client certificate, or None to keep
+// ureq's defaults when neither is set.
+fn tls_config(network: &NetworkConfig) -> Result<Option<Arc<rustls::ClientConfig>>> {
+    if network.ca_bundle.is_none() && network.client_cert.is_none() {
+        return Ok(None);
+    }
+    let mut roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    if let Some(bundle) = &network.ca_bundle {
+        let certs = read_certs(bundle)?;
+        let (added, _) = roots.add_parsable_certificates(certs);
+        if added == 0 {
+            bail!("No usable certificates in CA bundle '{}'", bundle.display());
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+    let config = match &network.client_cert {
+        Some(cert) => {
+            let key_path = network.client_key.as_deref().unwrap_or(cert);
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .with_context(|| format!("No private key found in '{}'", key_path.display()))?;
+            builder.with_client_auth_cert(read_certs(cert)?, key).context("Invalid client certificate or key")?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Some(Arc::new(config)))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificates from '{}'", path.display()))?;
+    if certs.is_empty() {
+        bail!("No certificates found in '{}'", path.display());
+    }
+    Ok(certs)
+}
@@ -0,0 +1,512 @@
+use crate::extract::unpack;
+use crate::release::GoFile;
+use crate::version::GoVersion;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_PREFIX: &str = "/usr/local";
+const VERSIONS_DIR: &str = "go-versions";
+const USER_DATA_DIR: &str = "go-installer";
+const CURRENT_LINK: &str = "current";
+const RECEIPT_FILE: &str = ".go-installer-receipt.json";
+
+// Written into every tree this tool installs. Uninstall refuses to touch trees without one.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Receipt {
+    pub version: String,
+    pub sha256: String,
+    // Seconds since the Unix epoch.
+    pub installed_at: u64,
+    // Symlinks created outside the tree that point into it.
+    #[serde(default)]
+    pub links: Vec<PathBuf>,
+}
+
+impl Receipt {
+    fn new(release: &GoFile) -> Self {
+        let installed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        Receipt { version: release.version.clone(), sha256: release.sha256.clone(), installed_at, links: Vec::new() }
+    }
+
+    pub fn read(go_dir: &Path) -> Result<Option<Self>> {
+        let path = go_dir.join(RECEIPT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        let receipt = serde_json::from_reader(file).with_context(|| format!("Corrupt install receipt '{}'", path.display()))?;
+        Ok(Some(receipt))
+    }
+
+    pub fn write(&self, go_dir: &Path) -> Result<()> {
+        let file = File::create(go_dir.join(RECEIPT_FILE))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+// The system install prefix. With a DESTDIR, files are written to <destdir>/<prefix> while
+// everything the tree refers to (symlinks, printed paths) stays relative to the final prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefix {
+    pub path: PathBuf,
+    pub destdir: Option<PathBuf>,
+}
+
+impl Prefix {
+    pub fn new(path: PathBuf, destdir: Option<PathBuf>) -> Result<Self> {
+        if !path.is_absolute() {
+            bail!("The install prefix must be an absolute path, got '{}'", path.display());
+        }
+        Ok(Prefix { path, destdir })
+    }
+
+    // Where files under the prefix are actually written.
+    fn on_disk(&self) -> PathBuf {
+        match &self.destdir {
+            Some(destdir) => destdir.join(self.path.strip_prefix("/").unwrap_or(&self.path)),
+            None => self.path.clone(),
+        }
+    }
+}
+
+// Who an installation is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    // System-wide under a prefix; needs sudo unless staged into a DESTDIR.
+    System(Prefix),
+    // Per-user under $XDG_DATA_HOME/go-installer, with binaries linked into ~/.local/bin.
+    User,
+}
+
+// Where Go trees live on disk.
+#[derive(Debug, Clone)]
+pub enum Layout {
+    // The classic single tree at <prefix>/go, replaced on every install.
+    Single { go_dir: PathBuf },
+    // One tree per version under <root>/<version>, with <root>/current pointing at the active one.
+    // When `link_dir` is set, the binaries of `current` are also linked there.
+    Versioned { root: PathBuf, link_dir: Option<PathBuf> },
+}
+
+impl Scope {
+    // The same scope with any DESTDIR dropped, i.e. the paths as seen once installed.
+    pub fn final_location(&self) -> Scope {
+        match self {
+            Scope::System(prefix) => Scope::System(Prefix { path: prefix.path.clone(), destdir: None }),
+            Scope::User => Scope::User,
+        }
+    }
+}
+
+impl Layout {
+    pub fn new(scope: &Scope, side_by_side: bool) -> Result<Self> {
+        Ok(match scope {
+            Scope::System(prefix) if side_by_side => Layout::Versioned {
+                root: prefix.on_disk().join(VERSIONS_DIR),
+                link_dir: None,
+            },
+            Scope::System(prefix) => Layout::Single { go_dir: prefix.on_disk().join("go") },
+            // Per-user installs are always side by side.
+            Scope::User => Layout::Versioned {
+                root: user_data_dir()?.join(USER_DATA_DIR),
+                link_dir: Some(home_dir()?.join(".local/bin")),
+            },
+        })
+    }
+
+    // The directory users should put on their PATH.
+    pub fn bin_dir(&self) -> PathBuf {
+        match self {
+            Layout::Single { go_dir } => go_dir.join("bin"),
+            Layout::Versioned { link_dir: Some(link_dir), .. } => link_dir.clone(),
+            Layout::Versioned { root, .. } => root.join(CURRENT_LINK).join("bin"),
+        }
+    }
+
+    // The tree a given version is (or would be) installed to.
+    pub fn version_dir(&self, version: &str) -> PathBuf {
+        match self {
+            Layout::Single { go_dir } => go_dir.clone(),
+            Layout::Versioned { root, .. } => root.join(version),
+        }
+    }
+
+    // Only the versioned layout can keep a version around without re-downloading it.
+    pub fn is_installed(&self, version: &str) -> bool {
+        matches!(self, Layout::Versioned { .. }) && self.version_dir(version).join("bin/go").is_file()
+    }
+
+    // Makes an installed version the active one: switches `current` and refreshes the binary links.
+    pub fn activate(&self, version: &str) -> Result<()> {
+        let Layout::Versioned { root, link_dir } = self else {
+            bail!("Switching versions needs a side-by-side install (--side-by-side or --user)");
+        };
+        let links = match link_dir {
+            Some(link_dir) => binary_links(&root.join(version), link_dir)?,
+            None => Vec::new(),
+        };
+        // Checked before switching, so a link that cannot be placed leaves `current` alone.
+        if let Some(link) = links.iter().find(|l| l.symlink_metadata().is_ok_and(|m| !m.file_type().is_symlink())) {
+            bail!("Refusing to overwrite '{}': it is not a symlink", link.display());
+        }
+
+        let previous = fs::read_link(root.join(CURRENT_LINK)).ok();
+        switch_current(root, version)?;
+        if let Some(link_dir) = link_dir {
+            if let Err(err) = link_binaries(root, link_dir, &links) {
+                // The version that was active before stays active.
+                let _ = match previous {
+                    Some(previous) => switch_current(root, &previous.to_string_lossy()),
+                    None => fs::remove_file(root.join(CURRENT_LINK)).map_err(Into::into),
+                };
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+// $XDG_DATA_HOME, falling back to ~/.local/share as the XDG spec prescribes.
+fn user_data_dir() -> Result<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Ok(dir),
+        _ => Ok(home_dir()?.join(".local/share")),
+    }
+}
+
+fn home_dir() -> Result<PathBuf> {
+    match env::var_os("HOME") {
+        Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+        _ => bail!("HOME is not set; cannot locate the per-user install directory"),
+    }
+}
+
+// A Go tree found on disk.
+#[derive(Debug)]
+pub struct InstalledGo {
+    pub version: String,
+    pub path: PathBuf,
+    // Whether this is the tree in use: the one the `current` symlink points at, or the single
+    // tree when there is no such link.
+    pub active: bool,
+}
+
+// Lists the single tree (if any) followed by the side-by-side versions, oldest first.
+pub fn installed_versions(scope: &Scope) -> Result<Vec<InstalledGo>> {
+    let mut installed = Vec::new();
+    let versioned_layout = Layout::new(scope, true)?;
+    let current = match &versioned_layout {
+        Layout::Versioned { root, .. } => fs::read_link(root.join(CURRENT_LINK)).ok(),
+        Layout::Single { .. } => None,
+    };
+
+    if let Layout::Single { go_dir } = Layout::new(scope, false)? {
+        if let Some(version) = read_version_file(&go_dir) {
+            installed.push(InstalledGo { version, path: go_dir, active: current.is_none() });
+        }
+    }
+
+    if let Layout::Versioned { root, .. } = versioned_layout {
+        let mut versioned = Vec::new();
+        if root.is_dir() {
+            for entry in fs::read_dir(&root)? {
+                let path = entry?.path();
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+                // Skips the `current` link and leftover staging directories.
+                if name == CURRENT_LINK || name.starts_with('.') || !path.join("bin/go").is_file() {
+                    continue;
+                }
+                let active = current.as_deref() == Some(Path::new(&name));
+                versioned.push(InstalledGo { version: name, path, active });
+            }
+        }
+        versioned.sort_by_key(|i| GoVersion::parse(&i.version).ok());
+        installed.extend(versioned);
+    }
+    Ok(installed)
+}
+
+// The first line of the VERSION file shipped in every Go tree, e.g. "go1.22.5".
+fn read_version_file(go_dir: &Path) -> Option<String> {
+    let contents = fs::read_to_string(go_dir.join("VERSION")).ok()?;
+    contents.lines().next().map(|l| l.trim().to_string())
+}
+
+// Installs a Go archive, read from a file or straight off the network, into the layout. In the
+// single layout the new tree replaces the old one; in the versioned layout it is added next to
+// the existing ones and made current.
+pub fn install_go(layout: &Layout, archive: &mut dyn Read, release: &GoFile) -> Result<PathBuf> {
+    let version = release.version.as_str();
+    let mut receipt = Receipt::new(release);
+    let target = layout.version_dir(version);
+    match layout {
+        Layout::Single { .. } => {
+            install_tree(archive, &target, version, &mut receipt, None, || Ok(()))?;
+        }
+        Layout::Versioned { link_dir, .. } => {
+            install_tree(archive, &target, version, &mut receipt, link_dir.as_deref(), || {
+                layout.activate(version)
+            })?;
+        }
+    }
+    Ok(target)
+}
+
+// Extracts into a sibling staging directory, checks the result, then swaps it into `target` by
+// rename. The previous tree is kept as a sibling backup until `finish` succeeds and is put back
+// if anything fails, so an interrupted or broken install never leaves the machine without Go.
+fn install_tree(
+    archive: &mut dyn Read,
+    target: &Path,
+    version: &str,
+    receipt: &mut Receipt,
+    link_dir: Option<&Path>,
+    finish: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    let parent = target.parent().context("Install directory has no parent")?;
+    let name = target.file_name().context("Install directory has no name")?.to_string_lossy();
+    let staging = parent.join(format!(".{}.staging", name));
+    let backup = parent.join(format!(".{}.backup", name));
+    fs::create_dir_all(parent)?;
+
+    // A backup without a tree means an earlier run died mid-swap.
+    if !target.exists() && backup.exists() {
+        println!("- Restoring Go installation left behind by an interrupted run...");
+        fs::rename(&backup, target)?;
+    }
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    println!("- Extracting Go archive...");
+    let staged = staging.join("go");
+    let prepared = unpack(archive, &staging)
+        .and_then(|_| {
+            if let Some(link_dir) = link_dir {
+                receipt.links = binary_links(&staged, link_dir)?;
+            }
+            receipt.write(&staged)
+        })
+        .and_then(|_| validate_tree(&staged, version));
+    if let Err(err) = prepared {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(err);
+    }
+
+    if target.exists() {
+        println!("- Backing up existing Go installation...");
+        if backup.exists() {
+            fs::remove_dir_all(&backup)?;
+        }
+        fs::rename(target, &backup)?;
+    }
+    let swapped = fs::rename(&staged, target)
+        .with_context(|| format!("Failed to move the new tree into '{}'", target.display()))
+        .and_then(|_| finish());
+    if let Err(err) = swapped {
+        println!("- Install failed, rolling back...");
+        if target.exists() {
+            fs::remove_dir_all(target)?;
+        }
+        if backup.exists() {
+            fs::rename(&backup, target)?;
+        }
+        let _ = fs::remove_dir_all(&staging);
+        return Err(err);
+    }
+
+    fs::remove_dir_all(&staging)?;
+    if backup.exists() {
+        fs::remove_dir_all(&backup)?;
+    }
+    Ok(())
+}
+
+// Makes sure the staged tree has a working `go` that reports the expected version.
+fn validate_tree(go_dir: &Path, version: &str) -> Result<()> {
+    let go = go_dir.join("bin/go");
+    if !go.is_file() {
+        bail!("The archive did not contain go/bin/go");
+    }
+    // GOTOOLCHAIN=local stops a go.mod in the working directory from switching toolchains.
+    let output = Command::new(&go)
+        .arg("version")
+        .env("GOTOOLCHAIN", "local")
+        .output()
+        .with_context(|| format!("Failed to run '{}'", go.display()))?;
+    let reported = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || !reported.starts_with(&format!("go version {} ", version)) {
+        bail!(
+            "The extracted Go does not work as expected.\n  Expected: go version {}\n  Got:      {}",
+            version,
+            reported.trim()
+        );
+    }
+    Ok(())
+}
+
+// Points <root>/current at an installed version. The link is built next to the old one and
+// renamed over it, so readers never observe a missing `current`.
+fn switch_current(root: &Path, version: &str) -> Result<()> {
+    if !root.join(version).join("bin/go").is_file() {
+        bail!("{} is not installed in '{}'", version, root.display());
+    }
+    let link = root.join(CURRENT_LINK);
+    let tmp_link = root.join(format!(".{}.tmp", CURRENT_LINK));
+    if tmp_link.symlink_metadata().is_ok() {
+        fs::remove_file(&tmp_link)?;
+    }
+    // A relative target keeps the root relocatable.
+    symlink(version, &tmp_link)?;
+    fs::rename(&tmp_link, &link).with_context(|| format!("Failed to update '{}'", link.display()))?;
+    Ok(())
+}
+
+// The links <link_dir>/<name> for every binary in <go_dir>/bin.
+fn binary_links(go_dir: &Path, link_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut links = Vec::new();
+    for entry in fs::read_dir(go_dir.join("bin"))? {
+        links.push(link_dir.join(entry?.file_name()));
+    }
+    links.sort();
+    Ok(links)
+}
+
+// Links each of `links` to the same binary under <root>/current/bin, so switching versions
+// never needs to touch them. Existing files that are not symlinks are never overwritten.
+fn link_binaries(root: &Path, link_dir: &Path, links: &[PathBuf]) -> Result<()> {
+    fs::create_dir_all(link_dir)?;
+    for link in links {
+        let name = link.file_name().context("Invalid link path")?;
+        match link.symlink_metadata() {
+            Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(link)?,
+            Ok(_) => bail!("Refusing to overwrite '{}': it is not a symlink", link.display()),
+            Err(_) => {}
+        }
+        symlink(root.join(CURRENT_LINK).join("bin").join(name), link)?;
+    }
+    Ok(())
+}
+
+// Removes a tree this tool installed: the single tree when `version` is None, otherwise a
+// side-by-side version. Links recorded in the receipt and a `current` link to it go too.
+pub fn uninstall_go(scope: &Scope, version: Option<&str>) -> Result<PathBuf> {
+    if *scope == Scope::User && version.is_none() {
+        bail!("Per-user installs are side by side; name the version to uninstall");
+    }
+    let layout = Layout::new(scope, version.is_some())?;
+    let go_dir = layout.version_dir(version.unwrap_or_default());
+    if !go_dir.exists() {
+        bail!("Nothing to uninstall: '{}' does not exist", go_dir.display());
+    }
+    let receipt = match Receipt::read(&go_dir)? {
+        Some(receipt) => receipt,
+        None => bail!(
+            "Refusing to remove '{}': it has no install receipt, so it was not installed by go-installer",
+            go_dir.display()
+        ),
+    };
+
+    // Links into `current` only go stale when this was the active version.
+    let mut owned = vec![go_dir.clone()];
+    if let Layout::Versioned { root, .. } = &layout {
+        let current = root.join(CURRENT_LINK);
+        if remove_link_into(&current, &go_dir)? {
+            owned.push(current);
+        }
+    }
+    for link in &receipt.links {
+        for tree in &owned {
+            remove_link_into(link, tree)?;
+        }
+    }
+    fs::remove_dir_all(&go_dir)?;
+    Ok(go_dir)
+}
+
+// Removes `link` only if it is a symlink resolving into `tree`; anything else is left alone.
+// Returns whether the link was removed.
+fn remove_link_into(link: &Path, tree: &Path) -> Result<bool> {
+    let Ok(target) = fs::read_link(link) else {
+        return Ok(false);
+    };
+    let target = link.parent().map(|p| p.join(&target)).unwrap_or(target);
+    if !target.starts_with(tree) {
+        return Ok(false);
+    }
+    println!("- Removing link {}", link.display());
+    fs::remove_file(link)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::PrivateDir;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    // A Go archive whose binaries are scripts reporting `version`, enough to pass validate_tree.
+    fn archive(version: &str, binaries: &[&str]) -> Vec<u8> {
+        let script = format!("#!/bin/sh\necho 'go version {} linux/amd64'\n", version);
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for name in binaries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o755);
+            header.set_size(script.len() as u64);
+            builder.append_data(&mut header, format!("go/bin/{}", name), script.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn release(version: &str) -> GoFile {
+        GoFile {
+            filename: format!("{}.linux-amd64.tar.gz", version),
+            os: "linux".to_string(),
+            arch: "amd64".to_string(),
+            version: version.to_string(),
+            sha256: "0".repeat(64),
+            size: 0,
+            kind: "archive".to_string(),
+        }
+    }
+
+    fn install(layout: &Layout, version: &str, binaries: &[&str]) -> Result<PathBuf> {
+        install_go(layout, &mut &archive(version, binaries)[..], &release(version))
+    }
+
+    #[test]
+    fn switches_current_and_links_binaries() {
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let layout = Layout::Versioned { root: dir.join("versions"), link_dir: Some(dir.join("bin")) };
+        install(&layout, "go1.22.5", &["go"]).unwrap();
+        install(&layout, "go1.22.6", &["go", "gofmt"]).unwrap();
+        assert_eq!(fs::read_link(dir.join("versions/current")).unwrap(), Path::new("go1.22.6"));
+        assert_eq!(fs::read_link(dir.join("bin/gofmt")).unwrap(), dir.join("versions/current/bin/gofmt"));
+        assert!(dir.join("versions/go1.22.5/bin/go").is_file());
+    }
+
+    #[test]
+    fn keeps_the_active_version_when_a_link_cannot_be_placed() {
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let layout = Layout::Versioned { root: dir.join("versions"), link_dir: Some(dir.join("bin")) };
+        install(&layout, "go1.22.5", &["go"]).unwrap();
+        // A file of the user's own where go1.22.6 would link gofmt.
+        fs::write(dir.join("bin/gofmt"), "mine").unwrap();
+
+        let err = install(&layout, "go1.22.6", &["go", "gofmt"]).unwrap_err().to_string();
+        assert!(err.contains("Refusing to overwrite"), "{}", err);
+        assert!(!dir.join("versions/go1.22.6").exists());
+        assert_eq!(fs::read_link(dir.join("versions/current")).unwrap(), Path::new("go1.22.5"));
+        assert!(dir.join("bin/go").is_file());
+        assert_eq!(fs::read_to_string(dir.join("bin/gofmt")).unwrap(), "mine");
+    }
+}
@@ -1,131 +1,734 @@
-use anyhow::{bail, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::Deserialize;
-use sha2::{Digest, Sha256};
+mod auth;
+mod bundle;
+mod cache;
+mod config;
+mod download;
+mod extract;
+mod gomod;
+mod http;
+mod install;
+mod mirror;
+mod release;
+mod retry;
+mod serve;
+mod signature;
+mod tempdir;
+mod throttle;
+mod version;
+
+use anyhow::{anyhow, bail, Result};
+use auth::Auth;
+use bundle::Bundle;
+use cache::{format_age, parse_size, Cache, DEFAULT_MAX_SIZE};
+use clap::{Args, Parser, Subcommand};
+use config::{CacheConfig, Config, DownloadConfig, NetworkConfig, Sources};
+use download::{compare_checksum, download_file, parse_checksum, stream_file, verify_checksum};
+use gomod::find_project_version;
+use http::Client;
+use indicatif::HumanBytes;
+use install::{install_go, installed_versions, uninstall_go, Layout, Prefix, Scope, DEFAULT_PREFIX};
+use mirror::rank_mirrors;
+use release::{fetch_releases, find_go_release, local_release, select_release, GoFile};
+use retry::RetryPolicy;
+use serve::serve;
+use signature::{download_signature, signature_path, Keyring};
 use std::env;
 use std::fs::{self, File};
-use std::io;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempdir::PrivateDir;
+use version::{GoVersion, VersionReq};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Work on a per-user install in $XDG_DATA_HOME/go-installer with binaries linked into
+    /// ~/.local/bin. Does not need sudo.
+    #[arg(long, global = true)]
+    user: bool,
+
+    /// Install prefix for system-wide installs.
+    #[arg(long, global = true, value_name = "DIR", default_value = DEFAULT_PREFIX, conflicts_with = "user")]
+    prefix: PathBuf,
+
+    /// Stage the install under DIR (DESTDIR semantics): files land in DIR/<prefix>, while paths
+    /// are recorded against the final prefix. Does not need sudo. Defaults to $DESTDIR, which
+    /// --user ignores.
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "user")]
+    destdir: Option<PathBuf>,
+
+    /// How many times to retry a network request after a timeout, connection error or 5xx.
+    #[arg(long, global = true, value_name = "N", default_value_t = 3)]
+    retries: u32,
+
+    /// Delay before the first retry in seconds; doubled (with jitter) for each further retry.
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 1.0)]
+    retry_delay: f64,
+
+    /// Base URL of a go.dev mirror, used for both release metadata and tarballs.
+    #[arg(long, global = true, value_name = "URL", env = "GO_INSTALLER_MIRROR")]
+    mirror: Option<String>,
+
+    /// Release metadata endpoint (overrides --mirror). Checksums are taken from here.
+    #[arg(long, global = true, value_name = "URL", env = "GO_INSTALLER_METADATA_URL")]
+    metadata_url: Option<String>,
+
+    /// Base URL for tarball downloads (overrides --mirror). Repeat it, or separate URLs with
+    /// commas, to list failover mirrors; the fastest healthy one is used.
+    #[arg(long, global = true, value_name = "URL", env = "GO_INSTALLER_DOWNLOAD_URL", value_delimiter = ',')]
+    download_url: Vec<String>,
+
+    /// Config file to read instead of $XDG_CONFIG_HOME/go-installer/config.toml or
+    /// /etc/go-installer/config.toml.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Proxy URL for all requests (default: HTTPS_PROXY/HTTP_PROXY/ALL_PROXY).
+    #[arg(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Comma-separated hosts and domains to reach without the proxy (default: NO_PROXY).
+    #[arg(long, global = true, value_name = "HOSTS")]
+    no_proxy: Option<String>,
+
+    /// PEM file with extra root certificates to trust, e.g. a corporate CA.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_CA_BUNDLE")]
+    ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate for mirrors that require mutual TLS.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_CLIENT_CERT")]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key for --client-cert, if it is not in the certificate file.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_CLIENT_KEY", requires = "client_cert")]
+    client_key: Option<PathBuf>,
+
+    /// File holding a bearer token for the configured mirrors (default: $GO_INSTALLER_TOKEN).
+    /// Per-host credentials go in the config file or ~/.netrc.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_TOKEN_FILE")]
+    token_file: Option<PathBuf>,
+
+    /// Parallel connections per download when the server supports range requests (default 1).
+    /// An interrupted parallel download starts over instead of resuming.
+    #[arg(long, global = true, value_name = "N", env = "GO_INSTALLER_CONNECTIONS")]
+    connections: Option<usize>,
 
-const GO_DL_URL: &str = "https://go.dev/dl/";
-const GO_API_URL: &str = "https://go.dev/dl/?mode=json";
-const INSTALL_DIR: &str = "/usr/local";
+    /// Cap the bandwidth of all downloads together, e.g. 500K or 2M (bytes per second).
+    #[arg(long, global = true, value_name = "RATE", env = "GO_INSTALLER_LIMIT_RATE")]
+    limit_rate: Option<String>,
 
-// Structs to deserialize the JSON response from the Go API.
-#[derive(Deserialize, Debug)]
-struct GoRelease {
-    files: Vec<GoFile>,
+    /// Seconds allowed for establishing a connection (default 10).
+    #[arg(long, global = true, value_name = "SECS")]
+    connect_timeout: Option<f64>,
+
+    /// Seconds a connection may stay silent before it is given up (default 30, at most the
+    /// stall timeout).
+    #[arg(long, global = true, value_name = "SECS")]
+    read_timeout: Option<f64>,
+
+    /// Abort and retry a download whose throughput stays near zero for this many seconds
+    /// (default 30).
+    #[arg(long, global = true, value_name = "SECS")]
+    stall_timeout: Option<f64>,
+
+    /// Seconds allowed for each download attempt as a whole (default: no limit).
+    #[arg(long, global = true, value_name = "SECS")]
+    timeout: Option<f64>,
+
+    /// OpenPGP keyring trusted to sign releases instead of the Go signing key, e.g. for an
+    /// internal mirror that re-signs its archives.
+    #[arg(long, global = true, value_name = "FILE", env = "GO_INSTALLER_KEYRING")]
+    keyring: Option<PathBuf>,
+
+    /// Do not check the OpenPGP signatures (.asc) of release archives; only their checksums.
+    #[arg(long, global = true)]
+    skip_signature: bool,
+
+    /// Download cache directory (default: $XDG_CACHE_HOME/go-installer).
+    #[arg(long, global = true, value_name = "DIR", env = "GO_INSTALLER_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    install: InstallArgs,
 }
 
-#[derive(Deserialize, Debug)]
-struct GoFile {
-    filename: String,
-    os: String,
-    arch: String,
-    version: String,
-    sha256: String,
-    size: u64,
-    kind: String,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Make an already installed side-by-side version the active one.
+    Use {
+        /// Installed version to activate (e.g. go1.22.5).
+        #[arg(value_name = "GO_VERSION")]
+        go_version: String,
+    },
+    /// Remove a Go tree installed by this tool.
+    Uninstall {
+        /// Side-by-side version to remove (e.g. go1.21.13). Without it, the single Go tree is removed.
+        #[arg(value_name = "GO_VERSION")]
+        go_version: Option<String>,
+    },
+    /// Show installed Go versions and the releases available from go.dev.
+    List {
+        /// Include every release ever published, not just the current ones.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Download releases and their metadata into a bundle directory, for installing on machines
+    /// without network access (see --from-bundle).
+    Export {
+        /// Versions to include, in the same syntax as for installing. Defaults to the latest.
+        #[arg(value_name = "GO_VERSION")]
+        go_versions: Vec<String>,
+
+        /// Bundle directory to create or add to.
+        #[arg(long, short, value_name = "DIR")]
+        output: PathBuf,
+
+        /// Platforms to include as <os>-<arch>; repeat it or separate them with commas.
+        /// Defaults to this machine's.
+        #[arg(long, value_name = "OS-ARCH", value_delimiter = ',')]
+        platform: Vec<String>,
+    },
+    /// Run a go.dev-compatible mirror for other machines (--mirror http://HOST:PORT/dl/),
+    /// serving tarballs from the download cache and filling it from upstream on demand.
+    Serve {
+        /// Address and port to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:8080")]
+        listen: String,
+    },
+    /// Inspect or clean the cache of downloaded tarballs.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Show the cached tarballs, most recently used first.
+    List,
+    /// Remove the least recently used tarballs until the cache fits its size limit, along with
+    /// incomplete downloads.
+    Prune {
+        /// Size limit such as 500M or 2G (default: the config's cache.max_size, or 1G).
+        #[arg(long, value_name = "SIZE")]
+        max_size: Option<String>,
+    },
+    /// Remove every cached tarball.
+    Clear,
+}
+
+#[derive(Args, Debug)]
+struct InstallArgs {
+    /// Go version to install: an exact version (go1.21.13), a constraint (1.22.x, ~1.22,
+    /// ">=1.21,<1.23"), "latest" or "oldstable". Defaults to the latest stable release.
+    #[arg(value_name = "GO_VERSION")]
+    go_version: Option<String>,
+
+    /// Install the version declared by the go.work/go.mod `go` and `toolchain` directives in DIR
+    /// (or its parents).
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = ".", conflicts_with = "go_version")]
+    go_mod: Option<PathBuf>,
+
+    /// Keep each version in its own directory under the versions root and switch a `current`
+    /// symlink instead of replacing the single Go tree.
+    #[arg(long)]
+    side_by_side: bool,
+
+    /// Install this go<version>.linux-<arch>.tar.gz instead of downloading one. Its checksum
+    /// and signature are verified; the network is never contacted.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["go_version", "go_mod"])]
+    tarball: Option<PathBuf>,
+
+    /// Install from a bundle made by `export` instead of the network. GO_VERSION is resolved
+    /// against the releases in the bundle.
+    #[arg(long, value_name = "DIR", conflicts_with = "tarball")]
+    from_bundle: Option<PathBuf>,
+
+    /// Expected sha256 of --tarball: the hex digest or a .sha256 file (default: <FILE>.sha256).
+    #[arg(long, value_name = "DIGEST|FILE", requires = "tarball")]
+    sha256: Option<String>,
+
+    /// Detached signature of --tarball (default: <FILE>.asc).
+    #[arg(long, value_name = "FILE", requires = "tarball")]
+    signature: Option<PathBuf>,
+
+    /// Neither reuse nor keep downloaded tarballs.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Extract the archive while it downloads instead of saving it first. The new tree is only
+    /// put in place once the download's checksum and signature match. Implies --no-cache.
+    #[arg(long, conflicts_with_all = ["tarball", "from_bundle"])]
+    stream: bool,
 }
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
     println!("--- Go Installer ---");
-    if env::var("SUDO_USER").is_err() {
-        bail!("This must be run with sudo to install Go in '{}'.", INSTALL_DIR);
+    let scope = if cli.user {
+        Scope::User
+    } else {
+        // An exported DESTDIR is only a default for system installs, unlike an explicit --destdir.
+        let destdir = cli.destdir.or_else(|| env::var_os("DESTDIR").map(PathBuf::from));
+        Scope::System(Prefix::new(cli.prefix, destdir.filter(|d| !d.as_os_str().is_empty()))?)
+    };
+    let retry_delay = Duration::try_from_secs_f64(cli.retry_delay).map_err(|_| anyhow!("Invalid --retry-delay"))?;
+    let config = Config::load(cli.config.as_deref())?;
+    let network = NetworkConfig {
+        proxy: cli.proxy.or(config.network.proxy.clone()),
+        no_proxy: cli.no_proxy.or(config.network.no_proxy.clone()),
+        ca_bundle: cli.ca_bundle.or(config.network.ca_bundle.clone()),
+        client_cert: cli.client_cert.clone().or(config.network.client_cert.clone()),
+        client_key: match cli.client_cert {
+            Some(_) => cli.client_key,
+            None => config.network.client_key.clone(),
+        },
+    };
+    let download = DownloadConfig {
+        connections: cli.connections.or(config.download.connections),
+        rate_limit: cli.limit_rate.or(config.download.rate_limit.clone()),
+        connect_timeout: cli.connect_timeout.or(config.download.connect_timeout),
+        read_timeout: cli.read_timeout.or(config.download.read_timeout),
+        timeout: cli.timeout.or(config.download.timeout),
+        stall_timeout: cli.stall_timeout.or(config.download.stall_timeout),
+    };
+    let mut sources = Sources::resolve(
+        cli.mirror.as_deref(),
+        cli.metadata_url.as_deref(),
+        &cli.download_url,
+        &config.mirror,
+    );
+    let auth = Auth::load(&config.auth, cli.token_file.as_deref(), &mut sources)?;
+    let client = Client::new(RetryPolicy::new(cli.retries, retry_delay), &network, &download, auth)?;
+    let cache = Cache::open(cli.cache_dir.or(config.cache.dir.clone()))?;
+    let custom_keyring = cli.keyring.or(config.signature.keyring.clone());
+    let skip_signature = cli.skip_signature;
+    let keyring = |bundle: Option<&Bundle>| keyring(bundle, custom_keyring.as_deref(), skip_signature);
+    match cli.command {
+        Some(Command::Use { go_version }) => use_version(&scope, &go_version),
+        Some(Command::Uninstall { go_version }) => uninstall(&scope, go_version.as_deref()),
+        Some(Command::List { all }) => list_versions(&client, &sources, &scope, all),
+        Some(Command::Export { go_versions, output, platform }) => {
+            export(&client, &sources, keyring(None)?.as_ref(), &output, &go_versions, platform)
+        }
+        Some(Command::Serve { listen }) => serve(&client, &sources, &cache, cache_limit(&config.cache, None)?, &listen),
+        Some(Command::Cache { action }) => manage_cache(&cache, &config.cache, action),
+        None if cli.install.tarball.is_some() => install_local(&scope, keyring(None)?.as_ref(), &cli.install),
+        None if cli.install.from_bundle.is_some() => {
+            let bundle = Bundle::new(cli.install.from_bundle.as_deref().unwrap());
+            install_from_bundle(&scope, &bundle, keyring(Some(&bundle))?.as_ref(), &cli.install)
+        }
+        None => {
+            let keyring = keyring(None)?;
+            let cache = (!cli.install.no_cache && !cli.install.stream).then_some(&cache);
+            install(&client, &sources, &scope, cache, keyring.as_ref(), &config.cache, &cli.install)
+        }
     }
+}
 
-    // 1. Detect Architecture and Fetch Release Info from API
-    let os_arch = match env::consts::ARCH {
+// System-wide installs need root, except when staging into a DESTDIR.
+fn ensure_sudo(scope: &Scope) -> Result<()> {
+    if let Scope::System(prefix) = scope {
+        if prefix.destdir.is_none() && env::var("SUDO_USER").is_err() {
+            bail!("This must be run with sudo to install Go in '{}'.", prefix.path.display());
+        }
+    }
+    Ok(())
+}
+
+// The keys release signatures are checked against: --keyring, or else the keys a bundle was
+// exported with, or else the pinned Go signing key built into the binary. None when signatures
+// are skipped or this build lacks the key.
+fn keyring(bundle: Option<&Bundle>, custom: Option<&Path>, skip: bool) -> Result<Option<Keyring>> {
+    if skip {
+        println!("- Signature verification skipped (--skip-signature)");
+        return Ok(None);
+    }
+    let bundled = match bundle {
+        Some(bundle) => bundle.keyring()?,
+        None => None,
+    };
+    let keyring = match (custom, bundled) {
+        (Some(custom), _) => Keyring::custom(custom)?,
+        (None, Some(bundled)) => bundled,
+        (None, None) => match Keyring::embedded() {
+            Some(keyring) => keyring,
+            None => {
+                println!("- Signature verification skipped: this build has no Go signing key (see --keyring)");
+                return Ok(None);
+            }
+        },
+    };
+    Ok(Some(keyring))
+}
+
+// Checks the detached signature of a tarball whose checksum has already been verified.
+fn verify_signature(keyring: Option<&Keyring>, tarball_path: &Path, signature: &Path) -> Result<()> {
+    if let Some(keyring) = keyring {
+        let key = keyring.verify(tarball_path, signature)?;
+        println!("✔ Signature Verified (key {})", key);
+    }
+    Ok(())
+}
+
+// Maps the Rust architecture name to the one used by go.dev.
+fn detect_arch() -> Result<&'static str> {
+    Ok(match env::consts::ARCH {
         "x86_64" => "amd64",
         "aarch64" => "arm64",
         unsupported => bail!("Unsupported architecture: {}", unsupported),
-    };
-    println!("✔ Detected Architecture: {}", os_arch);
+    })
+}
 
-    let release_info = get_latest_go_release(os_arch)?;
-    println!("✔ Found Latest Go Version: {}", release_info.version);
+fn install(
+    client: &Client,
+    sources: &Sources,
+    scope: &Scope,
+    cache: Option<&Cache>,
+    keyring: Option<&Keyring>,
+    cache_config: &CacheConfig,
+    args: &InstallArgs,
+) -> Result<()> {
+    let version_req = requested_version(args)?;
+    ensure_sudo(scope)?;
+    let layout = Layout::new(scope, args.side_by_side)?;
+    let final_layout = Layout::new(&scope.final_location(), args.side_by_side)?;
 
-    // 2. Download Tarball
-    let download_url = format!("{}{}", GO_DL_URL, release_info.filename);
-    let tarball_path = env::temp_dir().join(&release_info.filename);
-    download_file(&download_url, &tarball_path, release_info.size)?;
+    // 1. Detect Architecture and Fetch Release Info from API
+    let os_arch = detect_arch()?;
+    println!("✔ Detected Architecture: {}", os_arch);
 
-    // 3. Verify Checksum (using API data)
-    verify_checksum(&release_info.sha256, &tarball_path)?;
-    println!("✔ Checksum Verified");
+    // An exact version that was downloaded before needs no release lookup at all.
+    let cached = match (&version_req, cache) {
+        (VersionReq::Exact(version), Some(cache)) => cache.find(&version.to_string(), os_arch),
+        _ => None,
+    };
+    let release_info = match cached {
+        Some(file) => {
+            println!("✔ Found Go Version: {} (in the download cache)", file.version);
+            file
+        }
+        None => {
+            let file = find_go_release(client, sources, os_arch, &version_req)?;
+            println!("✔ Found Go Version: {} ({})", file.version, version_req);
+            file
+        }
+    };
 
-    // 4. Install
-    install_go(&tarball_path)?;
-    println!("✔ Go Installed to {}/go", INSTALL_DIR);
+    if layout.is_installed(&release_info.version) {
+        layout.activate(&release_info.version)?;
+        println!("✔ {} is already installed, switched to it", release_info.version);
+        print_path_instructions(&final_layout);
+        return Ok(());
+    }
+    if args.stream {
+        return stream_install(client, sources, scope, &layout, &final_layout, keyring, &release_info);
+    }
 
-    // 5. Final User Instruction
-    println!("\n--- ACTION REQUIRED ---");
-    println!("Go is installed. To complete setup, add Go to your PATH.");
-    println!("Run this command or add it to your shell profile (~/.profile, ~/.bashrc, etc.):");
-    println!("\n  echo 'export PATH=$PATH:{}/go/bin' >> ~/.profile && source ~/.profile\n", INSTALL_DIR);
+    // 2. Download Tarball, unless the cache already holds it. Without a cache it goes to a
+    // private scratch directory that is removed, with the signature, when this returns.
+    let scratch;
+    let tarball_path = match cache {
+        Some(cache) => cache.tarball_path(&release_info)?,
+        None => {
+            scratch = PrivateDir::new("go-installer")?;
+            scratch.join(&release_info.filename)
+        }
+    };
+    match cache {
+        Some(cache) if tarball_path.is_file() && verify_checksum(&release_info.sha256, &tarball_path).is_ok() => {
+            cache.touch(&tarball_path);
+            println!("✔ Using cached {} (checksum verified)", tarball_path.display());
+        }
+        _ => download_and_verify(client, sources, &release_info, &tarball_path)?,
+    }
+    let signature = signature_path(&tarball_path);
+    if keyring.is_some() && !signature.is_file() {
+        download_signature(client, &sources.tarball_urls(&release_info.filename), &signature)?;
+    }
+    if let Err(err) = verify_signature(keyring, &tarball_path, &signature) {
+        // A bad signature is fetched again next time.
+        let _ = fs::remove_file(&signature);
+        return Err(err);
+    }
+
+    install_tarball(scope, &layout, &final_layout, &mut File::open(&tarball_path)?, &release_info)?;
 
-    fs::remove_file(&tarball_path)?;
+    if let Some(cache) = cache {
+        let max_size = cache_limit(cache_config, None)?;
+        let (removed, freed) = cache.prune(max_size, Some(&release_info.sha256), false)?;
+        if removed > 0 {
+            let (freed, max_size) = (HumanBytes(freed), HumanBytes(max_size));
+            println!("- Pruned {} cached tarball(s) ({}) to stay under {}", removed, freed, max_size);
+        }
+    }
     Ok(())
 }
 
-// Fetches release data and finds the latest stable archive for the given architecture.
-fn get_latest_go_release(arch: &str) -> Result<GoFile> {
-    let releases: Vec<GoRelease> = ureq::get(GO_API_URL).call()?.into_json()?;
-
-    // Find the latest stable release for Linux archives.
-    for release in releases {
-        if let Some(file) = release.files.into_iter().find(|f| {
-            f.os == "linux" && f.arch == arch && f.kind == "archive"
-        }) {
-            return Ok(file); // Return the first one found (latest version)
+// The version asked for on the command line or by go.mod, "latest" by default.
+fn requested_version(args: &InstallArgs) -> Result<VersionReq> {
+    match &args.go_mod {
+        Some(dir) => {
+            let (path, req) = find_project_version(dir)?;
+            println!("✔ Read Go Version {} from {}", req, path.display());
+            Ok(req)
         }
+        None => VersionReq::parse(args.go_version.as_deref().unwrap_or("latest")),
     }
-    bail!("Could not find a stable Go release for linux-{}", arch)
 }
 
-// Downloads a file with a progress bar.
-fn download_file(url: &str, path: &Path, total_size: u64) -> Result<()> {
-    let res = ureq::get(url).call()?;
-    let pb = ProgressBar::new(total_size);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")?
-        .progress_chars("=>-"));
-    pb.set_message(format!("Downloading {}", path.file_name().unwrap().to_str().unwrap()));
+// Installs from an exported bundle; the bundle's metadata snapshot takes the place of the
+// release API and supplies the checksums.
+fn install_from_bundle(scope: &Scope, bundle: &Bundle, keyring: Option<&Keyring>, args: &InstallArgs) -> Result<()> {
+    let version_req = requested_version(args)?;
+    ensure_sudo(scope)?;
+    let layout = Layout::new(scope, args.side_by_side)?;
+    let final_layout = Layout::new(&scope.final_location(), args.side_by_side)?;
+
+    let os_arch = detect_arch()?;
+    println!("✔ Detected Architecture: {}", os_arch);
+    let release_info = select_release(&bundle.releases()?, "linux", os_arch, &version_req)?;
+    println!("✔ Found Go Version: {} ({}, in {})", release_info.version, version_req, bundle.dir.display());
 
-    let mut file = File::create(path)?;
-    io::copy(&mut pb.wrap_read(res.into_reader()), &mut file)?;
+    let tarball_path = bundle.tarball(&release_info)?;
+    println!("✔ Checksum Verified (against the bundle's release metadata)");
+    verify_signature(keyring, &tarball_path, &signature_path(&tarball_path))?;
+
+    install_tarball(scope, &layout, &final_layout, &mut File::open(&tarball_path)?, &release_info)
+}
 
-    pb.finish_with_message("Download complete.");
+fn export(
+    client: &Client,
+    sources: &Sources,
+    keyring: Option<&Keyring>,
+    output: &Path,
+    go_versions: &[String],
+    platforms: Vec<String>,
+) -> Result<()> {
+    let reqs = match go_versions {
+        [] => vec![VersionReq::Latest],
+        _ => go_versions.iter().map(|v| VersionReq::parse(v)).collect::<Result<_>>()?,
+    };
+    let platforms = match platforms {
+        platforms if platforms.is_empty() => vec![format!("linux-{}", detect_arch()?)],
+        platforms => platforms,
+    };
+    let bundle = Bundle::new(output);
+    let exported = bundle.export(client, sources, keyring, &reqs, &platforms)?;
+    println!("✔ Exported {} archive(s) to {}", exported, bundle.dir.display());
+    println!("  Install on the target machine with: go-installer --from-bundle {} [GO_VERSION]", bundle.dir.display());
     Ok(())
 }
 
-// Verifies the SHA256 checksum using the expected hash from the API.
-fn verify_checksum(expected_checksum: &str, file_path: &Path) -> Result<()> {
-    let mut file = File::open(file_path)?;
-    let mut hasher = Sha256::new();
-    io::copy(&mut file, &mut hasher)?;
-    let calculated_checksum = format!("{:x}", hasher.finalize());
+// Installs a tarball that was copied onto the machine by other means, trusting the checksum
+// and signature given alongside it.
+fn install_local(scope: &Scope, keyring: Option<&Keyring>, args: &InstallArgs) -> Result<()> {
+    let tarball_path = args.tarball.as_deref().unwrap();
+    let checksum = match &args.sha256 {
+        Some(value) => value.clone(),
+        None => {
+            let mut default = tarball_path.as_os_str().to_owned();
+            default.push(".sha256");
+            let default = PathBuf::from(default);
+            if !default.is_file() {
+                bail!(
+                    "No checksum for '{}': pass --sha256 or put it in '{}'",
+                    tarball_path.display(),
+                    default.display()
+                );
+            }
+            default.to_string_lossy().into_owned()
+        }
+    };
+    let release_info = local_release(tarball_path, parse_checksum(&checksum)?)?;
+    ensure_sudo(scope)?;
+    let layout = Layout::new(scope, args.side_by_side)?;
+    let final_layout = Layout::new(&scope.final_location(), args.side_by_side)?;
 
-    if calculated_checksum != expected_checksum {
+    let os_arch = detect_arch()?;
+    if release_info.os != "linux" || release_info.arch != os_arch {
         bail!(
-            "Checksum mismatch!\n  Expected:   {}\n  Calculated: {}",
-            expected_checksum, calculated_checksum
+            "'{}' is for {}-{}, but this machine is linux-{}",
+            tarball_path.display(),
+            release_info.os,
+            release_info.arch,
+            os_arch
         );
     }
+    println!("✔ Found Go Version: {} (from {})", release_info.version, tarball_path.display());
+
+    verify_checksum(&release_info.sha256, tarball_path)?;
+    println!("✔ Checksum Verified (against {})", checksum);
+    let signature = args.signature.clone().unwrap_or_else(|| signature_path(tarball_path));
+    verify_signature(keyring, tarball_path, &signature)?;
+
+    install_tarball(scope, &layout, &final_layout, &mut File::open(tarball_path)?, &release_info)
+}
+
+// Steps shared by every install source once the tarball is verified (or, when streaming, while
+// it is being verified).
+fn install_tarball(
+    scope: &Scope,
+    layout: &Layout,
+    final_layout: &Layout,
+    archive: &mut dyn Read,
+    release: &GoFile,
+) -> Result<()> {
+    // 4. Install
+    let go_dir = install_go(layout, archive, release)?;
+    println!("✔ Go Installed to {}", go_dir.display());
+    if let Scope::System(Prefix { destdir: Some(_), .. }) = scope {
+        println!("✔ Staged for final location {}", final_layout.version_dir(&release.version).display());
+    }
+
+    // 5. Final User Instruction
+    print_path_instructions(final_layout);
     Ok(())
 }
 
-// Removes any old installation and extracts the new one.
-fn install_go(tarball_path: &Path) -> Result<()> {
-    let go_path = PathBuf::from(INSTALL_DIR).join("go");
-    if go_path.exists() {
-        println!("- Removing existing Go installation...");
-        fs::remove_dir_all(&go_path)?;
+// Downloads and extracts in a single pass. The tarball never touches the disk, and the staged
+// tree is only put in place once the download has matched its checksum and signature.
+fn stream_install(
+    client: &Client,
+    sources: &Sources,
+    scope: &Scope,
+    layout: &Layout,
+    final_layout: &Layout,
+    keyring: Option<&Keyring>,
+    release: &GoFile,
+) -> Result<()> {
+    let tarball_urls = sources.tarball_urls(&release.filename);
+    // The signature is the only file written, into a private directory removed afterwards.
+    let scratch = PrivateDir::new("go-installer")?;
+    let signature = signature_path(&scratch.join(&release.filename));
+    if keyring.is_some() {
+        download_signature(client, &tarball_urls, &signature)?;
+    }
+    let download_urls = rank_mirrors(client, &tarball_urls, release.size);
+    stream_file(
+        client,
+        &download_urls,
+        release,
+        &sources.metadata_url,
+        keyring.map(|keyring| (keyring, signature.as_path())),
+        |archive| install_tarball(scope, layout, final_layout, archive, release),
+    )
+}
+
+fn download_and_verify(client: &Client, sources: &Sources, release: &GoFile, tarball_path: &Path) -> Result<()> {
+    let download_urls = rank_mirrors(client, &sources.tarball_urls(&release.filename), release.size);
+    let checksum = download_file(client, &download_urls, tarball_path, release.size)?;
+
+    // 3. Verify Checksum (using API data)
+    if let Err(err) = compare_checksum(&release.sha256, &checksum) {
+        let _ = fs::remove_file(tarball_path);
+        return Err(err);
+    }
+    println!("✔ Checksum Verified (against {})", sources.metadata_url);
+    Ok(())
+}
+
+// Switches the side-by-side `current` symlink to an installed version.
+fn use_version(scope: &Scope, go_version: &str) -> Result<()> {
+    ensure_sudo(scope)?;
+    let version = GoVersion::parse(go_version)?.to_string();
+    let layout = Layout::new(scope, true)?;
+    layout.activate(&version)?;
+    let final_layout = Layout::new(&scope.final_location(), true)?;
+    println!("✔ Now using {}", version);
+    print_path_instructions(&final_layout);
+    Ok(())
+}
+
+fn uninstall(scope: &Scope, go_version: Option<&str>) -> Result<()> {
+    ensure_sudo(scope)?;
+    let version = go_version.map(GoVersion::parse).transpose()?.map(|v| v.to_string());
+    let go_dir = uninstall_go(scope, version.as_deref())?;
+    println!("✔ Removed {}", go_dir.display());
+    Ok(())
+}
+
+// Prints the installed trees, then the remote releases with their stability and availability.
+fn list_versions(client: &Client, sources: &Sources, scope: &Scope, all: bool) -> Result<()> {
+    let os_arch = detect_arch()?;
+    let installed = installed_versions(scope)?;
+
+    println!("Installed:");
+    if installed.is_empty() {
+        println!("  (none)");
+    }
+    for go in &installed {
+        let marker = if go.active { "*" } else { " " };
+        println!("{} {:<12} {}", marker, go.version, go.path.display());
+    }
+
+    println!("\nAvailable:");
+    for release in fetch_releases(client, sources, all)? {
+        let marker = if installed.iter().any(|i| i.active && i.version == release.version) { "*" } else { " " };
+        let stability = if release.stable { "stable" } else { "unstable" };
+        let platform = match release.linux_archive(os_arch) {
+            Some(_) => format!("linux-{}", os_arch),
+            None => format!("(no linux-{} archive)", os_arch),
+        };
+        let status = if installed.iter().any(|i| i.version == release.version) { "installed" } else { "" };
+        let line = format!("{} {:<12} {:<9} {:<24} {}", marker, release.version, stability, platform, status);
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+fn manage_cache(cache: &Cache, config: &CacheConfig, action: CacheAction) -> Result<()> {
+    match action {
+        CacheAction::List => {
+            let entries = cache.entries()?;
+            println!("Cached tarballs in {}:", cache.dir.display());
+            if entries.is_empty() {
+                println!("  (none)");
+            }
+            for entry in &entries {
+                let status = if entry.path.is_some() { "" } else { " (incomplete)" };
+                let platform = format!("{}-{}", entry.file.os, entry.file.arch);
+                let size = HumanBytes(entry.size).to_string();
+                println!(
+                    "  {:<12} {:<13} {:>10}  used {}{}",
+                    entry.file.version,
+                    platform,
+                    size,
+                    format_age(entry.last_used),
+                    status
+                );
+            }
+            let total: u64 = entries.iter().map(|e| e.size).sum();
+            println!("\nTotal: {} (limit {})", HumanBytes(total), HumanBytes(cache_limit(config, None)?));
+        }
+        CacheAction::Prune { max_size } => {
+            let max_size = cache_limit(config, max_size.as_deref())?;
+            let (removed, freed) = cache.prune(max_size, None, true)?;
+            println!("✔ Removed {} cache entries, freed {}", removed, HumanBytes(freed));
+        }
+        CacheAction::Clear => {
+            let freed = cache.clear()?;
+            println!("✔ Cleared {}, freed {}", cache.dir.display(), HumanBytes(freed));
+        }
     }
-    println!("- Extracting Go archive...");
-    let tar_gz = File::open(tarball_path)?;
-    let tar = flate2::read::GzDecoder::new(tar_gz);
-    let mut archive = tar::Archive::new(tar);
-    archive.unpack(INSTALL_DIR)?;
     Ok(())
 }
+
+// The cache size limit: the flag, then the config file, then DEFAULT_MAX_SIZE.
+fn cache_limit(config: &CacheConfig, flag: Option<&str>) -> Result<u64> {
+    match flag.or(config.max_size.as_deref()) {
+        Some(size) => parse_size(size),
+        None => Ok(DEFAULT_MAX_SIZE),
+    }
+}
+
+fn print_path_instructions(layout: &Layout) {
+    println!("\n--- ACTION REQUIRED ---");
+    println!("Go is installed. To complete setup, add Go to your PATH.");
+    println!("Run this command or add it to your shell profile (~/.profile, ~/.bashrc, etc.):");
+    println!("\n  echo 'export PATH=$PATH:{}' >> ~/.profile && source ~/.profile\n", layout.bin_dir().display());
+}
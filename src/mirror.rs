@@ -0,0 +1,59 @@
+use crate::http::Client;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Orders tarball URLs for download: mirrors that answered a HEAD request with the expected size
+// come first, fastest first; the rest keep their configured order at the end as a last resort.
+pub fn rank_mirrors(client: &Client, urls: &[String], expected_size: u64) -> Vec<String> {
+    if urls.len() < 2 {
+        return urls.to_vec();
+    }
+
+    let results: Vec<Result<Duration, String>> = thread::scope(|scope| {
+        let probes: Vec<_> = urls.iter().map(|url| scope.spawn(move || probe(client, url, expected_size))).collect();
+        probes.into_iter().map(|p| p.join().unwrap_or_else(|_| Err("probe panicked".to_string()))).collect()
+    });
+
+    let mut healthy = Vec::new();
+    let mut unhealthy = Vec::new();
+    for (url, result) in urls.iter().zip(results) {
+        match result {
+            Ok(latency) => healthy.push((latency, url.clone())),
+            Err(reason) => {
+                println!("- Mirror {} is unavailable ({})", host(url), reason);
+                unhealthy.push(url.clone());
+            }
+        }
+    }
+    // A stable sort keeps the configured order between mirrors that are equally fast.
+    healthy.sort_by_key(|(latency, _)| *latency);
+    if let Some((latency, url)) = healthy.first() {
+        println!("✔ Selected Mirror: {} ({} ms)", host(url), latency.as_millis());
+    }
+    healthy.into_iter().map(|(_, url)| url).chain(unhealthy).collect()
+}
+
+fn probe(client: &Client, url: &str, expected_size: u64) -> Result<Duration, String> {
+    let start = Instant::now();
+    let res = client.head(url).timeout(PROBE_TIMEOUT).call().map_err(|e| match e {
+        ureq::Error::Status(code, _) => format!("HTTP {}", code),
+        ureq::Error::Transport(t) => t.message().map(str::to_string).unwrap_or_else(|| t.kind().to_string()),
+    })?;
+    let latency = start.elapsed();
+    match res.header("Content-Length").and_then(|l| l.parse::<u64>().ok()) {
+        Some(size) if size != expected_size => Err(format!("serves {} bytes, expected {}", size, expected_size)),
+        _ => Ok(latency),
+    }
+}
+
+// The scheme and host part of a URL, for messages. User info is left out so that credentials
+// are never printed.
+pub fn host(url: &str) -> String {
+    let after_scheme = url.find("://").map(|i| i + 3).unwrap_or(0);
+    let authority_end = url[after_scheme..].find(['/', '?', '#']).map_or(url.len(), |i| after_scheme + i);
+    let authority = &url[after_scheme..authority_end];
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    format!("{}{}", &url[..after_scheme], host)
+}
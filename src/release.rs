@@ -0,0 +1,144 @@
+use crate::config::Sources;
+use crate::http::Client;
+use crate::version::{GoVersion, VersionReq};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// Structs to deserialize the JSON response from the Go API.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GoRelease {
+    pub version: String,
+    pub stable: bool,
+    pub files: Vec<GoFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GoFile {
+    pub filename: String,
+    pub os: String,
+    pub arch: String,
+    pub version: String,
+    pub sha256: String,
+    pub size: u64,
+    pub kind: String,
+}
+
+impl GoRelease {
+    // The Linux archive for the given architecture, if this release ships one.
+    pub fn linux_archive(&self, arch: &str) -> Option<&GoFile> {
+        self.files.iter().find(|f| f.is_linux_archive(arch))
+    }
+}
+
+impl GoFile {
+    pub fn is_linux_archive(&self, arch: &str) -> bool {
+        self.is_archive("linux", arch)
+    }
+
+    pub fn is_archive(&self, os: &str, arch: &str) -> bool {
+        self.os == os && self.arch == arch && self.kind == "archive"
+    }
+}
+
+// Describes a local tarball from its go.dev file name (go1.22.5.linux-amd64.tar.gz), for
+// installs that never talk to the release API.
+pub fn local_release(path: &Path, sha256: String) -> Result<GoFile> {
+    let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+    let parsed = filename
+        .strip_suffix(".tar.gz")
+        .and_then(|stem| stem.rsplit_once('.'))
+        .and_then(|(version, platform)| Some((version, platform.split_once('-')?)))
+        .filter(|(version, _)| GoVersion::parse(version).is_ok());
+    let Some((version, (os, arch))) = parsed else {
+        bail!("'{}' is not named like a Go release archive (go<version>.<os>-<arch>.tar.gz)", path.display());
+    };
+    let size = fs::metadata(path).with_context(|| format!("Cannot read '{}'", path.display()))?.len();
+    Ok(GoFile {
+        version: version.to_string(),
+        os: os.to_string(),
+        arch: arch.to_string(),
+        sha256,
+        size,
+        kind: "archive".to_string(),
+        filename,
+    })
+}
+
+// Resolves a version requirement to the matching Linux archive for the given architecture.
+pub fn find_go_release(client: &Client, sources: &Sources, arch: &str, req: &VersionReq) -> Result<GoFile> {
+    // The latest release is always among the current ones; anything else may be an old release.
+    let releases = fetch_releases(client, sources, !matches!(req, VersionReq::Latest))?;
+    select_release(&releases, "linux", arch, req)
+}
+
+// Picks the archive for the given platform that best satisfies a version requirement from an
+// already fetched release list (newest first).
+pub fn select_release(releases: &[GoRelease], os: &str, arch: &str, req: &VersionReq) -> Result<GoFile> {
+    match req {
+        VersionReq::Latest => get_latest_go_release(releases, os, arch),
+        _ => get_matching_go_release(releases, os, arch, req),
+    }
+}
+
+// Fetches the current releases, or every release ever published when `all` is set. Newest first.
+pub fn fetch_releases(client: &Client, sources: &Sources, all: bool) -> Result<Vec<GoRelease>> {
+    client.get_json(&sources.releases_url(all))
+}
+
+// Finds the latest stable archive for the given platform.
+fn get_latest_go_release(releases: &[GoRelease], os: &str, arch: &str) -> Result<GoFile> {
+    for release in releases.iter().filter(|r| r.stable) {
+        if let Some(file) = release.files.iter().find(|f| f.is_archive(os, arch)) {
+            return Ok(file.clone()); // Return the first one found (latest version)
+        }
+    }
+    bail!("Could not find a stable Go release for {}-{}", os, arch)
+}
+
+// Searches the release list (including old and unstable releases) for the best match.
+fn get_matching_go_release(releases: &[GoRelease], os: &str, arch: &str, req: &VersionReq) -> Result<GoFile> {
+    let mut candidates: Vec<(GoVersion, GoFile)> = releases
+        .iter()
+        .flat_map(|release| &release.files)
+        .filter(|f| f.is_archive(os, arch))
+        .filter_map(|f| GoVersion::parse(&f.version).ok().map(|v| (v, f.clone())))
+        .collect();
+
+    if let Some(best) = req.select(candidates.iter().map(|(v, _)| v)) {
+        let index = candidates.iter().position(|(v, _)| *v == best).unwrap();
+        return Ok(candidates.swap_remove(index).1);
+    }
+
+    let available: Vec<GoVersion> = candidates.into_iter().map(|(v, _)| v).collect();
+    bail!(
+        "No Go release matching {} is available for {}-{}.\n  Nearby versions: {}",
+        req,
+        os,
+        arch,
+        nearby_versions(req, &available).join(", ")
+    )
+}
+
+// Picks a handful of versions close to the requested one: same minor line first, otherwise the newest releases.
+fn nearby_versions(req: &VersionReq, available: &[GoVersion]) -> Vec<String> {
+    const MAX_SUGGESTIONS: usize = 5;
+    let mut sorted = available.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    sorted.reverse();
+
+    if let VersionReq::Exact(wanted) = req {
+        let same_line: Vec<String> = sorted
+            .iter()
+            .filter(|v| v.minor_line() == wanted.minor_line())
+            .take(MAX_SUGGESTIONS)
+            .map(|v| v.to_string())
+            .collect();
+        if !same_line.is_empty() {
+            return same_line;
+        }
+    }
+    sorted.iter().take(MAX_SUGGESTIONS).map(|v| v.to_string()).collect()
+}
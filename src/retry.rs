@@ -0,0 +1,92 @@
+use anyhow::{Error, Result};
+use std::io;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How often and how patiently transient network failures are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // Retries after the first attempt; 0 disables retrying.
+    pub retries: u32,
+    // Delay before the first retry; doubled for every further one.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, base_delay: Duration) -> Self {
+        RetryPolicy { retries, base_delay, max_delay: Duration::from_secs(60) }
+    }
+
+    // Runs `op`, retrying transient failures with exponential backoff and jitter.
+    // `what` describes the operation in retry messages.
+    pub fn run<T>(&self, what: &str, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    attempt += 1;
+                    let delay = self.delay(attempt);
+                    println!(
+                        "- {} failed ({}), retrying in {:.1}s (retry {}/{})...",
+                        what,
+                        describe(&err),
+                        delay.as_secs_f64(),
+                        attempt,
+                        self.retries
+                    );
+                    thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // base * 2^(attempt-1), capped, then scaled into [50%, 100%] so that many clients failing
+    // together do not retry in lockstep.
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1 << (attempt - 1).min(16)).min(self.max_delay);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let jitter = 0.5 + f64::from(nanos % 1000) / 2000.0;
+        backoff.mul_f64(jitter)
+    }
+}
+
+// A short reason for retry messages; ureq's own messages repeat the URL.
+fn describe(err: &Error) -> String {
+    match err.root_cause().downcast_ref::<ureq::Error>() {
+        Some(ureq::Error::Status(code, _)) => format!("HTTP {}", code),
+        Some(ureq::Error::Transport(transport)) => {
+            transport.message().map(str::to_string).unwrap_or_else(|| transport.kind().to_string())
+        }
+        None => err.root_cause().to_string(),
+    }
+}
+
+// Timeouts, connection failures/resets and 5xx responses are worth retrying; anything else
+// (404, checksum mismatches, bad input) will fail the same way again.
+fn is_transient(err: &Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<ureq::Error>() {
+            return match err {
+                ureq::Error::Status(code, _) => (500..=599).contains(code),
+                ureq::Error::Transport(transport) => {
+                    matches!(transport.kind(), ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io)
+                }
+            };
+        }
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::Interrupted
+            );
+        }
+        false
+    })
+}
@@ -0,0 +1,476 @@
+use crate::cache::Cache;
+use crate::config::Sources;
+use crate::http::Client;
+use crate::release::{fetch_releases, GoFile, GoRelease};
+use crate::signature::{download_signature, signature_path};
+use crate::version::GoVersion;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server};
+
+// Clients use http://<host>/dl/ as their mirror, like https://go.dev/dl/.
+const PATH_PREFIX: &str = "/dl/";
+// How long an upstream release list is served before it is fetched again.
+const METADATA_TTL: Duration = Duration::from_secs(300);
+
+// A go.dev-compatible mirror: release metadata is proxied from upstream (falling back to what
+// the cache holds when upstream is unreachable) and tarballs are served from the download cache,
+// which fills itself from upstream the first time a tarball is requested.
+struct Mirror<'a> {
+    client: &'a Client,
+    sources: &'a Sources,
+    cache: &'a Cache,
+    max_size: u64,
+    // Upstream release lists keyed by `include=all`, with the time they were fetched.
+    metadata: Mutex<HashMap<bool, (Instant, Vec<GoRelease>)>>,
+    // File names of tarballs currently being copied into the cache.
+    filling: Arc<Mutex<HashSet<String>>>,
+}
+
+// Serves the mirror on `listen` (e.g. 0.0.0.0:8080) until the process is stopped.
+pub fn serve(client: &Client, sources: &Sources, cache: &Cache, max_size: u64, listen: &str) -> Result<()> {
+    let server = Server::http(listen).map_err(|e| anyhow!("Cannot listen on {}: {}", listen, e))?;
+    println!("✔ Serving a Go download mirror on http://{}{}", listen, PATH_PREFIX);
+    println!("  Upstream: {}, cache: {}", sources.metadata_url, cache.dir.display());
+    Mirror::new(client, sources, cache, max_size).run(&server);
+    Ok(())
+}
+
+impl<'a> Mirror<'a> {
+    fn new(client: &'a Client, sources: &'a Sources, cache: &'a Cache, max_size: u64) -> Self {
+        Mirror {
+            client,
+            sources,
+            cache,
+            max_size,
+            metadata: Mutex::new(HashMap::new()),
+            filling: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Answers each request on a thread of its own.
+    fn run(&self, server: &Server) {
+        thread::scope(|scope| {
+            for request in server.incoming_requests() {
+                scope.spawn(move || self.handle(request));
+            }
+        });
+    }
+
+    fn handle(&self, request: Request) {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let response = match (request.method(), path.strip_prefix(PATH_PREFIX)) {
+            (Method::Get | Method::Head, Some("")) if query.split('&').any(|p| p == "mode=json") => {
+                self.metadata(query.split('&').any(|p| p == "include=all"))
+            }
+            (Method::Get | Method::Head, Some(filename)) if !filename.is_empty() => self.tarball(&request, filename),
+            _ => Ok(status(404)),
+        };
+        let response = response.unwrap_or_else(|err| {
+            println!("- {} {} failed: {:#}", request.method(), url, err);
+            status(502)
+        });
+        let _ = request.respond(response);
+    }
+
+    fn metadata(&self, all: bool) -> Result<ResponseBox> {
+        let json = serde_json::to_string_pretty(&self.releases(all)?)?;
+        Ok(Response::from_string(json).with_header(header("Content-Type", "application/json")).boxed())
+    }
+
+    // The upstream release list, refreshed every METADATA_TTL. When upstream cannot be reached
+    // the last list fetched is used, or else a list of what the cache holds.
+    fn releases(&self, all: bool) -> Result<Vec<GoRelease>> {
+        let mut metadata = self.metadata.lock().unwrap();
+        if let Some((fetched, releases)) = metadata.get(&all) {
+            if fetched.elapsed() < METADATA_TTL {
+                return Ok(releases.clone());
+            }
+        }
+        match fetch_releases(self.client, self.sources, all) {
+            Ok(releases) => {
+                metadata.insert(all, (Instant::now(), releases.clone()));
+                Ok(releases)
+            }
+            Err(err) => {
+                println!("- Upstream metadata unavailable ({:#}), serving the last known releases", err);
+                match metadata.get(&all) {
+                    Some((_, releases)) => Ok(releases.clone()),
+                    None => self.cached_releases(),
+                }
+            }
+        }
+    }
+
+    // The fully cached tarballs as a release list, newest first.
+    fn cached_releases(&self) -> Result<Vec<GoRelease>> {
+        let mut releases: Vec<GoRelease> = Vec::new();
+        for entry in self.cache.entries()?.into_iter().filter(|e| e.path.is_some()) {
+            match releases.iter_mut().find(|r| r.version == entry.file.version) {
+                Some(release) => release.files.push(entry.file),
+                None => {
+                    let stable = GoVersion::parse(&entry.file.version).is_ok_and(|v| v.is_stable());
+                    releases.push(GoRelease { version: entry.file.version.clone(), stable, files: vec![entry.file] });
+                }
+            }
+        }
+        releases.sort_by_key(|r| Reverse(GoVersion::parse(&r.version).ok()));
+        Ok(releases)
+    }
+
+    fn tarball(&self, request: &Request, filename: &str) -> Result<ResponseBox> {
+        if let Some(tarball) = filename.strip_suffix(".asc") {
+            return self.signature(tarball);
+        }
+        let cached = self.cache.entries()?.into_iter().find(|e| e.file.filename == filename);
+        let (file, path) = match cached {
+            Some(entry) => (entry.file, entry.path),
+            // Only names from the release metadata are ever looked up, so paths from the request
+            // never reach the file system.
+            None => match self.releases(true)?.into_iter().flat_map(|r| r.files).find(|f| f.filename == filename) {
+                Some(file) => (file, None),
+                None => return Ok(status(404)),
+            },
+        };
+        let etag = format!("\"{}\"", file.sha256);
+        let range = requested_range(request, &etag);
+
+        if let Some(path) = path {
+            self.cache.touch(&path);
+            return serve_file(File::open(path)?, file.size, range, &etag);
+        }
+        if *request.method() == Method::Head {
+            // Not advertising range support makes clients fetch an uncached tarball in one
+            // piece, which fills the cache, rather than in parallel ranges, which would not.
+            let headers = vec![header("ETag", &etag)];
+            let response = Response::new(200.into(), headers, io::empty(), Some(file.size as usize), None);
+            return Ok(response.with_chunked_threshold(usize::MAX).boxed());
+        }
+        // Partial requests, and requests for a tarball that is already being cached, are passed
+        // through; only a full download fills the cache.
+        let fill = range.is_none() && self.filling.lock().unwrap().insert(file.filename.clone());
+        let response = self.upstream(&file, range).and_then(|upstream| match fill {
+            true => self.fill(&file, upstream, &etag),
+            false => Ok(pass_through(upstream, &file, &etag)),
+        });
+        if fill && response.is_err() {
+            self.filling.lock().unwrap().remove(&file.filename);
+        }
+        response
+    }
+
+    // A tarball's detached signature, fetched from upstream once and kept in its cache entry.
+    fn signature(&self, tarball: &str) -> Result<ResponseBox> {
+        let cached = self.cache.entries()?.into_iter().find(|e| e.file.filename == tarball).map(|e| e.file);
+        let file = match cached {
+            Some(file) => file,
+            None => match self.releases(true)?.into_iter().flat_map(|r| r.files).find(|f| f.filename == tarball) {
+                Some(file) => file,
+                None => return Ok(status(404)),
+            },
+        };
+        let path = signature_path(&self.cache.tarball_path(&file)?);
+        if !path.is_file() {
+            download_signature(self.client, &self.sources.tarball_urls(tarball), &path)?;
+        }
+        let length = fs::metadata(&path)?.len();
+        let response = Response::new(200.into(), Vec::new(), File::open(path)?, Some(length as usize), None);
+        Ok(response.with_header(header("Content-Type", "text/plain")).boxed())
+    }
+
+    fn fill(&self, file: &GoFile, upstream: ureq::Response, etag: &str) -> Result<ResponseBox> {
+        println!("- Caching {} from upstream", file.filename);
+        let path = self.cache.tarball_path(file)?;
+        let fill = CacheFill::new(upstream.into_reader(), path, file.clone(), self)?;
+        Ok(sized(200, fill, file.size, etag))
+    }
+
+    // Fetches the tarball from the first upstream mirror that answers.
+    fn upstream(&self, file: &GoFile, range: Option<(u64, Option<u64>)>) -> Result<ureq::Response> {
+        let urls = self.sources.tarball_urls(&file.filename);
+        let mut attempt = 0;
+        self.client.retry(&format!("Upstream request for {}", file.filename), || {
+            let mut request = self.client.get(&urls[attempt % urls.len()]);
+            attempt += 1;
+            if let Some((start, end)) = range {
+                let end = end.map(|e| e.to_string()).unwrap_or_default();
+                request = request.set("Range", &format!("bytes={}-{}", start, end));
+            }
+            Ok(request.call()?)
+        })
+    }
+}
+
+// Copies an upstream download into the cache while it streams to the client. The copy is kept
+// only if it is complete and matches the checksum; a client disconnecting midway discards it.
+struct CacheFill {
+    upstream: Box<dyn Read + Send + Sync>,
+    part: File,
+    part_path: PathBuf,
+    path: PathBuf,
+    hasher: Sha256,
+    written: u64,
+    file: GoFile,
+    cache: Cache,
+    max_size: u64,
+    filling: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CacheFill {
+    fn new(upstream: Box<dyn Read + Send + Sync>, path: PathBuf, file: GoFile, mirror: &Mirror) -> Result<Self> {
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        Ok(CacheFill {
+            upstream,
+            part: File::create(&part_path)?,
+            part_path,
+            path,
+            hasher: Sha256::new(),
+            written: 0,
+            file,
+            cache: mirror.cache.clone(),
+            max_size: mirror.max_size,
+            filling: mirror.filling.clone(),
+        })
+    }
+
+    fn finish(&mut self) {
+        let checksum = format!("{:x}", self.hasher.finalize_reset());
+        if self.written != self.file.size || checksum != self.file.sha256 {
+            println!("- Discarded upstream copy of {}: size or checksum mismatch", self.file.filename);
+            return;
+        }
+        if fs::rename(&self.part_path, &self.path).is_ok() {
+            println!("✔ Cached {}", self.file.filename);
+            let _ = self.cache.prune(self.max_size, Some(&self.file.sha256), false);
+        }
+    }
+}
+
+impl Read for CacheFill {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.upstream.read(buf)?;
+        if n == 0 {
+            self.finish();
+        } else {
+            self.part.write_all(&buf[..n])?;
+            self.hasher.update(&buf[..n]);
+            self.written += n as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for CacheFill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.part_path);
+        self.filling.lock().unwrap().remove(&self.file.filename);
+    }
+}
+
+fn pass_through(upstream: ureq::Response, file: &GoFile, etag: &str) -> ResponseBox {
+    let length = upstream.header("Content-Length").and_then(|l| l.parse().ok()).unwrap_or(file.size);
+    match (upstream.status(), upstream.header("Content-Range").map(str::to_string)) {
+        (206, Some(content_range)) => {
+            sized(206, upstream.into_reader(), length, etag).with_header(header("Content-Range", &content_range))
+        }
+        _ => sized(200, upstream.into_reader(), length, etag),
+    }
+}
+
+// Serves a cached tarball, honoring a single "bytes=<start>-[<end>]" range.
+fn serve_file(mut file: File, size: u64, range: Option<(u64, Option<u64>)>, etag: &str) -> Result<ResponseBox> {
+    let Some((start, end)) = range else {
+        return Ok(sized(200, file, size, etag));
+    };
+    if start >= size {
+        return Ok(status(416).with_header(header("Content-Range", &format!("bytes */{}", size))));
+    }
+    let end = end.unwrap_or(size - 1).min(size - 1);
+    file.seek(SeekFrom::Start(start))?;
+    let response = sized(206, file.take(end + 1 - start), end + 1 - start, etag);
+    Ok(response.with_header(header("Content-Range", &format!("bytes {}-{}/{}", start, end, size))))
+}
+
+// The range a client asked for. Ranges this mirror does not support (suffixes, several ranges)
+// and ranges made stale by a changed file (If-Range) are ignored, so the whole file is sent.
+fn requested_range(request: &Request, etag: &str) -> Option<(u64, Option<u64>)> {
+    if request_header(request, "If-Range").is_some_and(|validator| validator != etag) {
+        return None;
+    }
+    let (start, end) = request_header(request, "Range")?.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().ok().filter(|&end| end >= start)?),
+    };
+    Some((start, end))
+}
+
+fn request_header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+fn sized<R: Read + Send + 'static>(code: u16, body: R, length: u64, etag: &str) -> ResponseBox {
+    let headers = vec![header("ETag", etag), header("Accept-Ranges", "bytes")];
+    Response::new(code.into(), headers, body, Some(length as usize), None)
+        // A known length is always sent as Content-Length, which the download progress bar uses.
+        .with_chunked_threshold(usize::MAX)
+        .boxed()
+}
+
+fn status(code: u16) -> ResponseBox {
+    Response::empty(code).boxed()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MirrorConfig;
+    use crate::http::stand_in_server;
+    use crate::tempdir::PrivateDir;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const FILENAME: &str = "go1.22.5.linux-amd64.tar.gz";
+
+    fn tarball() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 253) as u8).collect()
+    }
+
+    fn release() -> GoRelease {
+        let file = GoFile {
+            filename: FILENAME.to_string(),
+            os: "linux".to_string(),
+            arch: "amd64".to_string(),
+            version: "go1.22.5".to_string(),
+            sha256: format!("{:x}", Sha256::digest(tarball())),
+            size: tarball().len() as u64,
+            kind: "archive".to_string(),
+        };
+        GoRelease { version: "go1.22.5".to_string(), stable: true, files: vec![file] }
+    }
+
+    // A stand-in for go.dev serving release() and its tarball, counting tarball requests.
+    fn upstream() -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let url = stand_in_server(move |request| {
+            let response = match request.url().strip_prefix(PATH_PREFIX).unwrap_or_default() {
+                path if path.starts_with("?mode=json") => {
+                    Response::from_string(serde_json::to_string(&vec![release()]).unwrap()).boxed()
+                }
+                FILENAME => {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Response::from_data(tarball()).boxed()
+                }
+                _ => status(404),
+            };
+            let _ = request.respond(response);
+        });
+        (format!("{}{}", url, PATH_PREFIX), hits)
+    }
+
+    // A mirror of upstream() with an empty cache, running until the test ends. Returns its /dl/
+    // URL, the cache (inside the returned directory) and the upstream tarball counter.
+    fn mirror() -> (String, Cache, PrivateDir, Arc<AtomicUsize>) {
+        let (upstream_url, hits) = upstream();
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let cache = Cache::open(Some(dir.join("cache"))).unwrap();
+        let sources = Sources::resolve(Some(&upstream_url), None, &[], &MirrorConfig::default());
+        // The mirror outlives this function, so it gets its own long-lived copies.
+        let client = Box::leak(Box::new(Client::for_tests()));
+        let (sources, mirror_cache) = (Box::leak(Box::new(sources)), Box::leak(Box::new(cache.clone())));
+        let mirror: &'static Mirror = Box::leak(Box::new(Mirror::new(client, sources, mirror_cache, u64::MAX)));
+        let url = stand_in_server(move |request| mirror.handle(request));
+        (format!("{}{}", url, PATH_PREFIX), cache, dir, hits)
+    }
+
+    // The status, Content-Range and body of a GET.
+    fn get(url: &str, headers: &[(&str, &str)]) -> (u16, Option<String>, Vec<u8>) {
+        let mut request = Client::for_tests().get(url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let res = match request.call() {
+            Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+            Err(err) => panic!("{}", err),
+        };
+        let (status, content_range) = (res.status(), res.header("Content-Range").map(str::to_string));
+        let mut body = Vec::new();
+        res.into_reader().read_to_end(&mut body).unwrap();
+        (status, content_range, body)
+    }
+
+    // Downloads the tarball through the mirror and waits for it to land in the cache.
+    fn fill(url: &str, cache: &Cache) {
+        assert_eq!(get(&format!("{}{}", url, FILENAME), &[]), (200, None, tarball()));
+        for _ in 0..100 {
+            if cache.entries().unwrap().iter().any(|e| e.file.filename == FILENAME && e.path.is_some()) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("{} was not cached", FILENAME);
+    }
+
+    #[test]
+    fn serves_release_metadata() {
+        let (url, _cache, _dir, _) = mirror();
+        for query in ["?mode=json", "?mode=json&include=all"] {
+            let (status, _, body) = get(&format!("{}{}", url, query), &[]);
+            assert_eq!(status, 200);
+            let releases: Vec<GoRelease> = serde_json::from_slice(&body).unwrap();
+            assert_eq!(releases.len(), 1);
+            assert_eq!(releases[0].files[0].sha256, release().files[0].sha256);
+        }
+        assert_eq!(get(&format!("{}go1.22.5.linux-arm64.tar.gz", url), &[]).0, 404);
+    }
+
+    #[test]
+    fn fills_the_cache_on_a_full_download() {
+        let (url, cache, _dir, hits) = mirror();
+        fill(&url, &cache);
+        let entry = cache.entries().unwrap().into_iter().find(|e| e.file.filename == FILENAME).unwrap();
+        assert_eq!(fs::read(entry.path.unwrap()).unwrap(), tarball());
+        // The second download is served from the cache.
+        assert_eq!(get(&format!("{}{}", url, FILENAME), &[]), (200, None, tarball()));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn serves_ranges_from_the_cache() {
+        let (url, cache, _dir, hits) = mirror();
+        fill(&url, &cache);
+        let url = format!("{}{}", url, FILENAME);
+        let etag = format!("\"{}\"", release().files[0].sha256);
+
+        let (status, content_range, body) = get(&url, &[("Range", "bytes=100-199")]);
+        assert_eq!((status, content_range.as_deref()), (206, Some("bytes 100-199/100000")));
+        assert_eq!(body, tarball()[100..200]);
+
+        let (status, content_range, body) = get(&url, &[("Range", "bytes=99990-"), ("If-Range", &etag)]);
+        assert_eq!((status, content_range.as_deref()), (206, Some("bytes 99990-99999/100000")));
+        assert_eq!(body, tarball()[99990..]);
+
+        let (status, content_range, _) = get(&url, &[("Range", "bytes=100000-")]);
+        assert_eq!((status, content_range.as_deref()), (416, Some("bytes */100000")));
+
+        // A range for some other version of the file gets the whole file.
+        assert_eq!(get(&url, &[("Range", "bytes=100-"), ("If-Range", "\"stale\"")]), (200, None, tarball()));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
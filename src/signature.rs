@@ -0,0 +1,299 @@
+use crate::http::Client;
+use crate::mirror::host;
+use crate::tempdir::PrivateDir;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+// Go release archives are signed with Google's Linux package signing key. The armored key ships
+// inside the binary, so verification works offline; its primary key fingerprint is checked
+// against this pin on every signature, so a wrong key can never slip in unnoticed. A build whose
+// key file holds no key block yet verifies checksums only, unless a keyring is given.
+const GO_SIGNING_KEY: &str = include_str!("go-signing-key.asc");
+const GO_SIGNING_KEY_FINGERPRINT: &str = "EB4C1BFD4F042F6DDDCCEC917721F63BD38B4796";
+// Where a bundle keeps the keys its archives were verified against: the Go signing key, or a
+// keyring given with --keyring, whose keys are trusted without a pin.
+pub const GO_SIGNING_KEY_FILE: &str = "go-signing-key.pub";
+pub const CUSTOM_KEYRING_FILE: &str = "release-keys.pub";
+
+// The keys trusted to sign Go releases.
+#[derive(Debug)]
+pub struct Keyring {
+    key: Vec<u8>,
+    // Primary key fingerprint a good signature must come from; None trusts every key in `key`.
+    pinned: Option<&'static str>,
+}
+
+impl Keyring {
+    // A keyring supplied for an internal mirror: any key in it may sign releases.
+    pub fn custom(path: &Path) -> Result<Keyring> {
+        Ok(Keyring { key: read_keyring(path)?, pinned: None })
+    }
+
+    // A copy of the Go signing key stored at `path`, e.g. in a bundle.
+    pub fn pinned(path: &Path) -> Result<Keyring> {
+        Ok(Keyring { key: read_keyring(path)?, pinned: Some(GO_SIGNING_KEY_FINGERPRINT) })
+    }
+
+    // The Go signing key built into the binary, if this build has one.
+    pub fn embedded() -> Option<Keyring> {
+        GO_SIGNING_KEY.contains("-----BEGIN PGP PUBLIC KEY BLOCK-----").then(|| Keyring {
+            key: GO_SIGNING_KEY.as_bytes().to_vec(),
+            pinned: Some(GO_SIGNING_KEY_FINGERPRINT),
+        })
+    }
+
+    // The key material, for copying into a bundle.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    // The file name the keyring is stored under in a bundle.
+    pub fn bundle_file(&self) -> &'static str {
+        match self.pinned {
+            Some(_) => GO_SIGNING_KEY_FILE,
+            None => CUSTOM_KEYRING_FILE,
+        }
+    }
+
+    // Checks a detached signature with gpgv and returns the signing key's fingerprint. Fails
+    // unless the signature is good and, for the pinned key, made by that key.
+    pub fn verify(&self, file: &Path, signature: &Path) -> Result<String> {
+        let mut check = self.start(signature)?;
+        io::copy(&mut File::open(file)?, &mut check)?;
+        check.finish(file)
+    }
+
+    // Starts checking a detached signature against data that is then written to the returned
+    // check, so that a download can be verified while it streams.
+    pub fn start(&self, signature: &Path) -> Result<SignatureCheck> {
+        if !signature.is_file() {
+            bail!("Missing signature '{}'", signature.display());
+        }
+        // gpgv only reads binary keyrings, and a private home directory keeps it away from the
+        // user's own keys.
+        let home = PrivateDir::new("go-installer-gpg")?;
+        home.create("keyring.gpg")?.write_all(&dearmor(&self.key)?)?;
+
+        let child = Command::new("gpgv")
+            .arg("--homedir")
+            .arg(home.path())
+            .args(["--status-fd", "1", "--keyring"])
+            .arg(home.join("keyring.gpg"))
+            .arg(signature)
+            // The signed data is read from stdin.
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run gpgv; install GnuPG or pass --skip-signature")?;
+        Ok(SignatureCheck { child: Some(child), _home: home, pinned: self.pinned })
+    }
+}
+
+// A signature check in progress: gpgv reads the signed data as it is written here.
+pub struct SignatureCheck {
+    child: Option<Child>,
+    // Removed once gpgv has exited, see `Drop`.
+    _home: PrivateDir,
+    pinned: Option<&'static str>,
+}
+
+impl SignatureCheck {
+    // Waits for gpgv's verdict on everything written so far and returns the signing key's
+    // fingerprint.
+    pub fn finish(mut self, file: &Path) -> Result<String> {
+        let mut child = self.child.take().unwrap();
+        drop(child.stdin.take());
+        let output = child.wait_with_output()?;
+
+        let status = String::from_utf8_lossy(&output.stdout);
+        // [GNUPG:] VALIDSIG <fingerprint> <date> ... <primary key fingerprint>
+        let valid = status.lines().find_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "));
+        let (Some(valid), true) = (valid, output.status.success()) else {
+            let keyword = |word: &str| status.lines().any(|line| line.starts_with(&format!("[GNUPG:] {} ", word)));
+            let reason = if keyword("BADSIG") {
+                "the file does not match the signature"
+            } else if keyword("NO_PUBKEY") {
+                "it was made by a key that is not in the keyring"
+            } else if keyword("NODATA") {
+                "the signature file is not an OpenPGP signature"
+            } else {
+                "no valid signature"
+            };
+            bail!("Bad signature for '{}': {}", file.display(), reason);
+        };
+        let fields: Vec<&str> = valid.split_whitespace().collect();
+        let primary = fields.get(9).or(fields.first()).copied().unwrap_or_default();
+        if let Some(pinned) = self.pinned {
+            if !primary.eq_ignore_ascii_case(pinned) {
+                bail!("'{}' is signed by key {}, not the Go signing key {}", file.display(), primary, pinned);
+            }
+        }
+        Ok(primary.to_string())
+    }
+}
+
+impl Write for SignatureCheck {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let stdin = self.child.as_mut().and_then(|c| c.stdin.as_mut()).unwrap();
+        match stdin.write(buf) {
+            // gpgv stops reading when the signature itself is unusable; `finish` says why.
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SignatureCheck {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// Fetches `<file>.asc` from the first mirror that has it. A mirror that fails, including with
+// a 404 because it only carries the archives, hands over to the next one.
+pub fn download_signature(client: &Client, tarball_urls: &[String], path: &Path) -> Result<()> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    let mut failure = None;
+    for (i, tarball_url) in tarball_urls.iter().enumerate() {
+        if i > 0 {
+            println!("- Failing over to mirror {} for {}", host(tarball_url), name);
+        }
+        let url = format!("{}.asc", tarball_url);
+        match client.retry(&format!("Download of {}", name), || Ok(client.get(&url).call()?)) {
+            Ok(res) => {
+                // A signature is a few hundred bytes; anything much larger is not one.
+                let mut signature = Vec::new();
+                res.into_reader().take(64 * 1024).read_to_end(&mut signature)?;
+                fs::write(path, signature)?;
+                return Ok(());
+            }
+            Err(err) => failure = Some(err),
+        }
+    }
+    // There is always at least one download URL.
+    Err(failure.unwrap())
+}
+
+fn read_keyring(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read keyring '{}'", path.display()))
+}
+
+// "<file>.asc", where a detached signature is expected next to its file.
+pub fn signature_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".asc");
+    PathBuf::from(path)
+}
+
+// Converts an ASCII-armored keyring to the binary form gpgv reads; binary keyrings pass through.
+fn dearmor(keyring: &[u8]) -> Result<Vec<u8>> {
+    let text = String::from_utf8_lossy(keyring);
+    if !text.trim_start().starts_with("-----BEGIN PGP") {
+        return Ok(keyring.to_vec());
+    }
+    let mut binary = Vec::new();
+    let mut in_headers = false;
+    // The base64 body of the block being read.
+    let mut body: Option<String> = None;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with("-----BEGIN PGP") {
+            in_headers = true;
+        } else if line.starts_with("-----END PGP") {
+            if let Some(body) = body.take() {
+                binary.extend(BASE64.decode(body).context("Invalid ASCII-armored keyring")?);
+            }
+        } else if in_headers {
+            if line.is_empty() {
+                in_headers = false;
+                body = Some(String::new());
+            }
+        } else if let Some(body) = body.as_mut() {
+            // Lines starting with '=' hold the armor checksum.
+            if !line.starts_with('=') {
+                body.push_str(line);
+            }
+        }
+    }
+    Ok(binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::PrivateDir;
+
+    // testdata/test-key.asc holds a primary key that only certifies and a subkey that signs, as
+    // the Go signing key does; testdata/signed.txt.asc is the subkey's signature of signed.txt.
+    const PRIMARY: &str = "43D236C43A2456123742B779137AB4A4D90BAFD6";
+    const SUBKEY: &str = "3C77EE8F17B441D6BE2BDADD7477634026D88B74";
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/testdata").join(name)
+    }
+
+    fn test_key(pinned: Option<&'static str>) -> Keyring {
+        Keyring { key: fs::read(testdata("test-key.asc")).unwrap(), pinned }
+    }
+
+    // The tag of the first OpenPGP packet, in either header format.
+    fn first_packet_tag(binary: &[u8]) -> u8 {
+        match binary[0] {
+            b if b & 0x40 != 0 => b & 0x3f,
+            b => (b >> 2) & 0x0f,
+        }
+    }
+
+    #[test]
+    fn dearmors_keyrings() {
+        let armored = fs::read(testdata("test-key.asc")).unwrap();
+        let binary = dearmor(&armored).unwrap();
+        // A transferable public key starts with its public key packet (tag 6).
+        assert_eq!(first_packet_tag(&binary), 6);
+        assert_eq!(dearmor(&binary).unwrap(), binary);
+    }
+
+    #[test]
+    fn embedded_key_is_a_public_key() {
+        if let Some(keyring) = Keyring::embedded() {
+            assert_eq!(first_packet_tag(&dearmor(keyring.key()).unwrap()), 6);
+        }
+    }
+
+    #[test]
+    fn reports_the_primary_key_of_a_subkey_signature() {
+        let fingerprint = test_key(None).verify(&testdata("signed.txt"), &testdata("signed.txt.asc")).unwrap();
+        assert_eq!(fingerprint, PRIMARY);
+        assert_ne!(fingerprint, SUBKEY);
+        test_key(Some(PRIMARY)).verify(&testdata("signed.txt"), &testdata("signed.txt.asc")).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_keys_when_pinned() {
+        let keyring = test_key(Some(GO_SIGNING_KEY_FINGERPRINT));
+        let err = keyring.verify(&testdata("signed.txt"), &testdata("signed.txt.asc")).unwrap_err().to_string();
+        assert!(err.contains(&format!("signed by key {}, not the Go signing key", PRIMARY)), "{}", err);
+    }
+
+    #[test]
+    fn rejects_modified_data() {
+        let dir = PrivateDir::new("go-installer-test").unwrap();
+        let file = dir.join("signed.txt");
+        fs::write(&file, "go1.22.6 test payload\n").unwrap();
+        let err = test_key(None).verify(&file, &testdata("signed.txt.asc")).unwrap_err().to_string();
+        assert!(err.contains("the file does not match the signature"), "{}", err);
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+// A directory only the current user can enter, removed with everything in it when dropped.
+// The installer often runs as root, so scratch files never go to a guessable path in a shared
+// temp directory where someone could have planted a symlink first.
+#[derive(Debug)]
+pub struct PrivateDir {
+    path: PathBuf,
+}
+
+impl PrivateDir {
+    // Creates "<tmp>/<prefix>-<random>"; fails rather than reuse anything already there.
+    pub fn new(prefix: &str) -> Result<PrivateDir> {
+        let mut random = [0u8; 8];
+        File::open("/dev/urandom")
+            .and_then(|mut f| f.read_exact(&mut random))
+            .context("Failed to read /dev/urandom")?;
+        let name: String = random.iter().map(|b| format!("{:02x}", b)).collect();
+        let path = env::temp_dir().join(format!("{}-{}", prefix, name));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&path)
+            .with_context(|| format!("Failed to create temporary directory '{}'", path.display()))?;
+        Ok(PrivateDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    // Creates a new file inside the directory; never opens one that already exists.
+    pub fn create(&self, name: &str) -> Result<File> {
+        let path = self.join(name);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to create '{}'", path.display()))
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,93 @@
+use indicatif::HumanBytes;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Throughput below this counts as no progress at all; keep-alive trickles do not hide a stall.
+const STALL_RATE: u64 = 1024;
+// How much unused rate can build up while nothing is read, as a burst allowance.
+const MAX_BURST: Duration = Duration::from_millis(250);
+
+// Pacing shared by every download of the process, so the rate cap holds across parallel
+// connections: an optional rate limit, and stall detection that aborts a download whose
+// throughput stays near zero for `stall_timeout`.
+#[derive(Debug)]
+pub struct Throttle {
+    rate: Option<u64>,
+    stall_timeout: Duration,
+    // When the bytes already let through will have been paid for at `rate`.
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(rate: Option<u64>, stall_timeout: Duration) -> Throttle {
+        Throttle { rate, stall_timeout, next: Mutex::new(Instant::now()) }
+    }
+
+    // Wraps a response body so that reading it observes the limits.
+    pub fn reader<R: Read>(&self, inner: R) -> Throttled<'_, R> {
+        Throttled { inner, throttle: self, window_start: Instant::now(), window_bytes: 0 }
+    }
+
+    // The limits in effect, for progress bar messages, e.g. "limit 1.00 MiB/s, stall 30s".
+    pub fn describe(&self) -> String {
+        let stall = format!("stall {}s", self.stall_timeout.as_secs_f64());
+        match self.rate {
+            Some(rate) => format!("limit {}/s, {}", HumanBytes(rate), stall),
+            None => stall,
+        }
+    }
+
+    // Waits until `bytes` more bytes fit under the rate limit.
+    fn take(&self, bytes: usize) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+            *next = (*next).max(earliest) + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            next.saturating_duration_since(now)
+        };
+        thread::sleep(wait);
+    }
+
+    // A rate cap below the stall threshold must not look like a stall.
+    fn stall_rate(&self) -> u64 {
+        self.rate.map_or(STALL_RATE, |rate| STALL_RATE.min(rate / 2))
+    }
+}
+
+pub struct Throttled<'a, R> {
+    inner: R,
+    throttle: &'a Throttle,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Small reads under a rate cap keep the progress smooth instead of bursty.
+        let max = match self.throttle.rate {
+            Some(rate) => (rate as usize / 8).max(1024).min(buf.len()),
+            None => buf.len(),
+        };
+        let n = self.inner.read(&mut buf[..max])?;
+        self.throttle.take(n);
+
+        self.window_bytes += n as u64;
+        let elapsed = self.window_start.elapsed();
+        if n > 0 && elapsed >= self.throttle.stall_timeout {
+            let stall_rate = self.throttle.stall_rate();
+            if (self.window_bytes as f64) < stall_rate as f64 * elapsed.as_secs_f64() {
+                let msg = format!("Download stalled: under {}/s for {}s", HumanBytes(stall_rate), elapsed.as_secs());
+                return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+            }
+            self.window_start = Instant::now();
+            self.window_bytes = 0;
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,404 @@
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+
+// Pre-release stage of a Go version. Declaration order gives beta < rc < final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Beta(u32),
+    Rc(u32),
+    Final,
+}
+
+// A parsed Go release version such as "go1.22.5", "go1.21rc2" or "go1.20".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GoVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub stage: Stage,
+}
+
+impl GoVersion {
+    // Parses the spelling used by the go.dev API; the "go" prefix is optional.
+    pub fn parse(input: &str) -> Result<Self> {
+        let text = input.trim();
+        let text = text.strip_prefix("go").unwrap_or(text);
+        let invalid = || anyhow!("Invalid Go version: '{}'", input);
+
+        let (numbers, stage) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+            Some(i) => {
+                let (numbers, suffix) = text.split_at(i);
+                let stage = if let Some(n) = suffix.strip_prefix("rc") {
+                    Stage::Rc(n.parse().map_err(|_| invalid())?)
+                } else if let Some(n) = suffix.strip_prefix("beta") {
+                    Stage::Beta(n.parse().map_err(|_| invalid())?)
+                } else {
+                    return Err(invalid());
+                };
+                (numbers, stage)
+            }
+            None => (text, Stage::Final),
+        };
+
+        let parts = numbers
+            .split('.')
+            .map(|p| p.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+        let (major, minor, patch) = match parts[..] {
+            [major, minor] => (major, minor, 0),
+            [major, minor, patch] if stage == Stage::Final => (major, minor, patch),
+            _ => return Err(invalid()),
+        };
+        Ok(GoVersion { major, minor, patch, stage })
+    }
+
+    pub fn is_stable(&self) -> bool {
+        self.stage == Stage::Final
+    }
+
+    // The "go1.22" line this version belongs to.
+    pub fn minor_line(&self) -> (u32, u32) {
+        (self.major, self.minor)
+    }
+}
+
+impl fmt::Display for GoVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "go{}.{}", self.major, self.minor)?;
+        match self.stage {
+            Stage::Beta(n) => write!(f, "beta{}", n),
+            Stage::Rc(n) => write!(f, "rc{}", n),
+            // Before Go 1.21 the first release of a line was spelled without ".0".
+            Stage::Final if self.patch == 0 && (self.major, self.minor) < (1, 21) => Ok(()),
+            Stage::Final => write!(f, ".{}", self.patch),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    // "~1.22" and "1.22.x": any release on the given minor line.
+    Tilde,
+    // "^1.22": any release with the same major version, at least the given one.
+    Caret,
+}
+
+// A version that may omit components, e.g. "1.22" in ">=1.22".
+#[derive(Clone, Copy, Debug)]
+struct Partial {
+    major: u32,
+    minor: Option<u32>,
+    patch: Option<u32>,
+    stage: Option<Stage>,
+}
+
+impl Partial {
+    fn parse(input: &str) -> Result<Self> {
+        let text = input.trim();
+        let text = text.strip_prefix("go").unwrap_or(text);
+        let text = text
+            .strip_suffix(".x")
+            .or_else(|| text.strip_suffix(".*"))
+            .unwrap_or(text);
+
+        if text.contains(|c: char| c.is_ascii_alphabetic()) {
+            let v = GoVersion::parse(text)?;
+            return Ok(Partial {
+                major: v.major,
+                minor: Some(v.minor),
+                patch: Some(v.patch),
+                stage: Some(v.stage),
+            });
+        }
+
+        let parts = text
+            .split('.')
+            .map(|p| p.parse::<u32>().map_err(|_| anyhow!("Invalid version in constraint: '{}'", input)))
+            .collect::<Result<Vec<_>>>()?;
+        match parts[..] {
+            [major] => Ok(Partial { major, minor: None, patch: None, stage: None }),
+            [major, minor] => Ok(Partial { major, minor: Some(minor), patch: None, stage: None }),
+            [major, minor, patch] => Ok(Partial { major, minor: Some(minor), patch: Some(patch), stage: None }),
+            _ => bail!("Invalid version in constraint: '{}'", input),
+        }
+    }
+
+    // The smallest final version covered by this partial version.
+    fn floor(&self) -> GoVersion {
+        GoVersion {
+            major: self.major,
+            minor: self.minor.unwrap_or(0),
+            patch: self.patch.unwrap_or(0),
+            stage: self.stage.unwrap_or(Stage::Final),
+        }
+    }
+
+    // Compares a concrete version against this partial one, ignoring omitted components.
+    fn compare(&self, v: &GoVersion) -> Ordering {
+        v.major
+            .cmp(&self.major)
+            .then_with(|| self.minor.map_or(Ordering::Equal, |m| v.minor.cmp(&m)))
+            .then_with(|| self.patch.map_or(Ordering::Equal, |p| v.patch.cmp(&p)))
+            .then_with(|| self.stage.map_or(Ordering::Equal, |s| v.stage.cmp(&s)))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Comparator {
+    op: Op,
+    version: Partial,
+}
+
+impl Comparator {
+    fn parse(input: &str) -> Result<Self> {
+        let text = input.trim();
+        let wildcard = text.ends_with(".x") || text.ends_with(".*");
+        let (op, rest) = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("=", Op::Exact),
+            ("~", Op::Tilde),
+            ("^", Op::Caret),
+        ]
+        .iter()
+        .find_map(|(prefix, op)| text.strip_prefix(prefix).map(|rest| (*op, rest)))
+        .unwrap_or((if wildcard { Op::Tilde } else { Op::Exact }, text));
+
+        let version = Partial::parse(rest)?;
+        // "1.x" spans the whole major version.
+        let op = if wildcard && version.minor.is_none() { Op::Caret } else { op };
+        if op == Op::Tilde && version.minor.is_none() {
+            bail!("'{}' needs a minor version, e.g. ~1.22", input);
+        }
+        Ok(Comparator { op, version })
+    }
+
+    fn matches(&self, v: &GoVersion) -> bool {
+        let ord = self.version.compare(v);
+        match self.op {
+            Op::Exact => ord == Ordering::Equal,
+            Op::Greater => ord == Ordering::Greater,
+            Op::GreaterEq => ord != Ordering::Less,
+            Op::Less => ord == Ordering::Less,
+            Op::LessEq => ord != Ordering::Greater,
+            Op::Tilde => {
+                v.major == self.version.major
+                    && Some(v.minor) == self.version.minor
+                    && *v >= self.version.floor()
+            }
+            Op::Caret => v.major == self.version.major && *v >= self.version.floor(),
+        }
+    }
+
+    // Pre-releases are only considered when the constraint names one explicitly.
+    fn allows_prerelease(&self) -> bool {
+        matches!(self.version.stage, Some(Stage::Beta(_)) | Some(Stage::Rc(_)))
+    }
+}
+
+// What the user asked for on the command line.
+#[derive(Clone, Debug)]
+pub enum VersionReq {
+    // Newest stable release.
+    Latest,
+    // Newest patch of the minor line before the newest stable one.
+    OldStable,
+    // One exact release, e.g. "go1.21.13" or "go1.23rc1".
+    Exact(GoVersion),
+    // Comma-separated comparators that must all match, e.g. ">=1.21,<1.23".
+    Range(Vec<Comparator>),
+}
+
+impl VersionReq {
+    pub fn parse(input: &str) -> Result<Self> {
+        let text = input.trim();
+        match text {
+            "" | "latest" | "stable" => return Ok(VersionReq::Latest),
+            "oldstable" | "previous" => return Ok(VersionReq::OldStable),
+            _ => {}
+        }
+
+        // A fully spelled version without operators is a pin.
+        if !text.contains(['<', '>', '=', '~', '^', ',', '*', 'x']) {
+            let dots = text.matches('.').count();
+            if text.starts_with("go") || dots == 2 || text.contains("rc") || text.contains("beta") {
+                return Ok(VersionReq::Exact(GoVersion::parse(text)?));
+            }
+        }
+
+        let comparators = text
+            .split(',')
+            .map(Comparator::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(VersionReq::Range(comparators))
+    }
+
+    pub fn matches(&self, v: &GoVersion) -> bool {
+        match self {
+            VersionReq::Latest | VersionReq::OldStable => v.is_stable(),
+            VersionReq::Exact(exact) => exact == v,
+            VersionReq::Range(comparators) => {
+                (v.is_stable() || comparators.iter().any(Comparator::allows_prerelease))
+                    && comparators.iter().all(|c| c.matches(v))
+            }
+        }
+    }
+
+    // Picks the best (highest) version satisfying the requirement.
+    pub fn select<'a, I>(&self, versions: I) -> Option<GoVersion>
+    where
+        I: IntoIterator<Item = &'a GoVersion>,
+    {
+        let mut candidates: Vec<GoVersion> = versions.into_iter().copied().filter(|v| self.matches(v)).collect();
+        candidates.sort();
+        match self {
+            VersionReq::OldStable => {
+                let newest_line = candidates.last()?.minor_line();
+                candidates.into_iter().rev().find(|v| v.minor_line() < newest_line)
+            }
+            _ => candidates.pop(),
+        }
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionReq::Latest => write!(f, "latest stable"),
+            VersionReq::OldStable => write!(f, "previous stable minor"),
+            VersionReq::Exact(v) => write!(f, "{}", v),
+            VersionReq::Range(comparators) => {
+                let parts: Vec<String> = comparators.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", parts.join(","))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            Op::Exact => "",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+        };
+        write!(f, "{}{}", op, self.version.major)?;
+        if let Some(minor) = self.version.minor {
+            write!(f, ".{}", minor)?;
+        }
+        match (self.version.patch, self.version.stage) {
+            (_, Some(Stage::Beta(n))) => write!(f, "beta{}", n),
+            (_, Some(Stage::Rc(n))) => write!(f, "rc{}", n),
+            (Some(patch), _) => write!(f, ".{}", patch),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(text: &str) -> GoVersion {
+        GoVersion::parse(text).unwrap()
+    }
+
+    fn select(req: &str) -> Option<String> {
+        let versions: Vec<GoVersion> = [
+            "go1.20", "go1.20.14", "go1.21rc2", "go1.21.0", "go1.21.13", "go1.22rc1", "go1.22.0", "go1.22.5",
+            "go1.23beta1", "go1.23rc1",
+        ]
+        .iter()
+        .map(|text| v(text))
+        .collect();
+        VersionReq::parse(req).unwrap().select(&versions).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn parses_go_spellings() {
+        assert_eq!(v("go1.22.5"), GoVersion { major: 1, minor: 22, patch: 5, stage: Stage::Final });
+        assert_eq!(v("1.21rc2"), GoVersion { major: 1, minor: 21, patch: 0, stage: Stage::Rc(2) });
+        assert_eq!(v("go1.20"), v("go1.20.0"));
+        assert!(GoVersion::parse("go1").is_err());
+        assert!(GoVersion::parse("go1.22.1rc1").is_err());
+        assert!(GoVersion::parse("go1.22alpha1").is_err());
+    }
+
+    #[test]
+    fn orders_prereleases_before_the_release() {
+        assert!(v("go1.23beta1") < v("go1.23beta2"));
+        assert!(v("go1.23beta2") < v("go1.23rc1"));
+        assert!(v("go1.23rc1") < v("go1.23rc2"));
+        assert!(v("go1.23rc2") < v("go1.23.0"));
+        assert!(v("go1.22.10") > v("go1.22.9"));
+        assert!(v("go1.20") < v("go1.20.1"));
+        assert!(v("go1.20.14") < v("go1.21.0"));
+    }
+
+    #[test]
+    fn displays_the_go_dev_spelling() {
+        // The first release of a line lost its ".0" only before Go 1.21.
+        assert_eq!(v("go1.20.0").to_string(), "go1.20");
+        assert_eq!(v("go1.21").to_string(), "go1.21.0");
+        assert_eq!(v("go1.23rc1").to_string(), "go1.23rc1");
+        assert_eq!(v("1.22.5").to_string(), "go1.22.5");
+    }
+
+    #[test]
+    fn parses_comparators() {
+        assert_eq!(Comparator::parse("1.22.x").unwrap().to_string(), "~1.22");
+        assert_eq!(Comparator::parse("1.*").unwrap().to_string(), "^1");
+        assert_eq!(Comparator::parse(">=go1.21").unwrap().to_string(), ">=1.21");
+        assert_eq!(Comparator::parse("<1.23rc1").unwrap().to_string(), "<1.23rc1");
+        assert!(Comparator::parse("~1").is_err());
+        assert!(Comparator::parse(">=1.x.2").is_err());
+    }
+
+    #[test]
+    fn selects_exact_versions() {
+        assert_eq!(select("go1.21.13").as_deref(), Some("go1.21.13"));
+        assert_eq!(select("1.21.0").as_deref(), Some("go1.21.0"));
+        assert_eq!(select("go1.20").as_deref(), Some("go1.20"));
+        assert_eq!(select("1.23rc1").as_deref(), Some("go1.23rc1"));
+        assert_eq!(select("go1.19.1"), None);
+    }
+
+    #[test]
+    fn selects_within_constraints() {
+        assert_eq!(select("latest").as_deref(), Some("go1.22.5"));
+        assert_eq!(select("1.22.x").as_deref(), Some("go1.22.5"));
+        assert_eq!(select("1.21").as_deref(), Some("go1.21.13"));
+        assert_eq!(select("~1.22").as_deref(), Some("go1.22.5"));
+        assert_eq!(select("~1.21.5").as_deref(), Some("go1.21.13"));
+        assert_eq!(select("^1.21").as_deref(), Some("go1.22.5"));
+        assert_eq!(select(">=1.21,<1.23").as_deref(), Some("go1.22.5"));
+        assert_eq!(select(">=1.20, <1.22").as_deref(), Some("go1.21.13"));
+        assert_eq!(select("<1.21").as_deref(), Some("go1.20.14"));
+        assert_eq!(select(">1.22.5"), None);
+    }
+
+    #[test]
+    fn selects_prereleases_only_when_named() {
+        assert_eq!(select(">=1.23beta1").as_deref(), Some("go1.23rc1"));
+        assert_eq!(select(">=1.22,<1.23").as_deref(), Some("go1.22.5"));
+        assert_eq!(select("~1.23"), None);
+    }
+
+    #[test]
+    fn selects_oldstable() {
+        assert_eq!(select("oldstable").as_deref(), Some("go1.21.13"));
+        assert_eq!(select("previous").as_deref(), Some("go1.21.13"));
+    }
+}